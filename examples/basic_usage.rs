@@ -28,14 +28,16 @@ fn main() {
     onpair16.compress_strings(&strings);
 
     // Decompress and verify the results
-    let mut buffer = vec![0u8; 256];
+    let mut buffer = Vec::with_capacity(256);
     for (i, user_id) in strings.iter().enumerate() {
         println!("\nString {}: \"{}\"", i, user_id);
 
-        let size = onpair.decompress_string(i, &mut buffer);
-        println!("- OnPair: \"{}\"", String::from_utf8_lossy(&buffer[..size]));
+        buffer.clear();
+        onpair.decompress_into(i, &mut buffer);
+        println!("- OnPair: \"{}\"", String::from_utf8_lossy(&buffer));
 
-        let size = onpair16.decompress_string(i, &mut buffer);
-        println!("- OnPair16: \"{}\"", String::from_utf8_lossy(&buffer[..size]));
+        buffer.clear();
+        onpair16.decompress_into(i, &mut buffer);
+        println!("- OnPair16: \"{}\"", String::from_utf8_lossy(&buffer));
     }
 }
\ No newline at end of file
@@ -0,0 +1,120 @@
+//! Compares searches on compressed strings with decompress-and-compare scans
+//!
+//! Usage: `cargo run --release --example compressed_search [FILE]`
+//! (add `--features regex` to include regular expression searches)
+//!
+//! Each line of FILE is a string of the collection. Without a file, a synthetic
+//! column of URL paths is used. Every search is checked against the oracle,
+//! which decompresses each string and tests it with the standard library.
+
+use onpair_rs::{OnPair, OnPair16};
+use std::time::Instant;
+
+const THRESHOLD: u16 = 5;
+const SEED: u64 = 42;
+
+fn main() {
+    let strings = match std::env::args().nth(1) {
+        Some(path) => std::fs::read_to_string(path)
+            .expect("failed to read input file")
+            .lines()
+            .map(str::to_owned)
+            .collect(),
+        None => synthetic_strings(1_000_000),
+    };
+
+    let mut onpair = OnPair::new(THRESHOLD);
+    onpair.compress_strings(&strings);
+    let mut onpair16 = OnPair16::new(THRESHOLD);
+    onpair16.compress_strings(&strings);
+
+    println!("{} strings\n", strings.len());
+    println!("{:<10} {:<40} {:>9} {:>16} {:>12}", "variant", "query", "matches", "compressed (ms)", "oracle (ms)");
+
+    let prefixes: [&[u8]; 4] = [b"/api/", b"/api/v2/users/", b"/static/img/", b"/nothing"];
+    for prefix in prefixes {
+        let query = format!("starts_with {:?}", String::from_utf8_lossy(prefix));
+        let oracle = |string: &[u8]| string.starts_with(prefix);
+        compare("OnPair", &query, || onpair.starts_with(prefix).collect(), oracle, |index| onpair.get(index), onpair.len());
+        compare("OnPair16", &query, || onpair16.starts_with(prefix).collect(), oracle, |index| onpair16.get(index), onpair16.len());
+    }
+
+    let suffixes: [&[u8]; 3] = [b".png", b"7.json", b"/nothing"];
+    for suffix in suffixes {
+        let query = format!("ends_with {:?}", String::from_utf8_lossy(suffix));
+        let oracle = |string: &[u8]| string.ends_with(suffix);
+        compare("OnPair", &query, || onpair.ends_with(suffix).collect(), oracle, |index| onpair.get(index), onpair.len());
+        compare("OnPair16", &query, || onpair16.ends_with(suffix).collect(), oracle, |index| onpair16.get(index), onpair16.len());
+    }
+
+    let patterns: [&[u8]; 3] = [b"orders/1", b"9.json", b"missing"];
+    for pattern in patterns {
+        let query = format!("contains {:?}", String::from_utf8_lossy(pattern));
+        let oracle = |string: &[u8]| contains(string, pattern);
+        compare("OnPair", &query, || onpair.contains(pattern).collect(), oracle, |index| onpair.get(index), onpair.len());
+        compare("OnPair16", &query, || onpair16.contains(pattern).collect(), oracle, |index| onpair16.get(index), onpair16.len());
+    }
+
+    let patterns: [&[u8]; 3] = [b"12", b"/css/", b".png"];
+    let query = format!("contains_any {:?}", patterns.map(String::from_utf8_lossy));
+    let oracle = |string: &[u8]| patterns.iter().any(|pattern| contains(string, pattern));
+    compare("OnPair", &query, || onpair.contains_any(&patterns).collect(), oracle, |index| onpair.get(index), onpair.len());
+    compare("OnPair16", &query, || onpair16.contains_any(&patterns).collect(), oracle, |index| onpair16.get(index), onpair16.len());
+
+    #[cfg(feature = "regex")]
+    for pattern in [r"^/api/v1/users/\d+\.json$", r"/(css|img)/9\d*\.", r"^/static/.*\.json$"] {
+        let regex = regex_automata::dfa::regex::Regex::new(pattern).unwrap();
+        let query = format!("regex {:?}", pattern);
+        let oracle = |string: &[u8]| regex.is_match(string);
+        compare("OnPair", &query, || onpair.regex_matches(pattern).unwrap().collect(), oracle, |index| onpair.get(index), onpair.len());
+        compare("OnPair16", &query, || onpair16.regex_matches(pattern).unwrap().collect(), oracle, |index| onpair16.get(index), onpair16.len());
+    }
+}
+
+/// Runs a search on the compressed strings and the same search on the decompressed strings,
+/// checks that they match the same strings and prints both timings
+fn compare(
+    variant: &str,
+    query: &str,
+    search: impl Fn() -> Vec<usize>,
+    oracle: impl Fn(&[u8]) -> bool,
+    get: impl Fn(usize) -> Vec<u8>,
+    n_strings: usize,
+) {
+    let start = Instant::now();
+    let matches = search();
+    let search_time = start.elapsed().as_secs_f64() * 1e3;
+
+    let start = Instant::now();
+    let expected: Vec<usize> = (0..n_strings).filter(|&index| oracle(&get(index))).collect();
+    let oracle_time = start.elapsed().as_secs_f64() * 1e3;
+
+    assert_eq!(matches, expected, "{} {} does not match the oracle", variant, query);
+    println!("{:<10} {:<40} {:>9} {:>16.2} {:>12.2}", variant, query, matches.len(), search_time, oracle_time);
+}
+
+/// Returns `true` if `pattern` occurs in `string`
+fn contains(string: &[u8], pattern: &[u8]) -> bool {
+    pattern.is_empty() || string.windows(pattern.len()).any(|window| window == pattern)
+}
+
+/// Generates a column of URL paths with a few shared prefixes and file extensions
+fn synthetic_strings(n: usize) -> Vec<String> {
+    const ROOTS: [&str; 5] = ["/api/v1/users/", "/api/v2/users/", "/api/v2/orders/", "/static/img/", "/static/css/"];
+    const EXTENSIONS: [&str; 4] = ["", ".json", ".png", ".css"];
+
+    // Small linear congruential generator, so the example needs no extra dependency
+    let mut state = SEED;
+    let mut random = move || {
+        state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (state >> 33) as usize
+    };
+
+    (0..n)
+        .map(|_| {
+            let root = ROOTS[random() % ROOTS.len()];
+            let extension = EXTENSIONS[random() % EXTENSIONS.len()];
+            format!("{}{}{}", root, random() % 100_000, extension)
+        })
+        .collect()
+}
@@ -0,0 +1,123 @@
+//! Compares sequential and parallel dictionary training
+//!
+//! Usage: `cargo run --release --features parallel --example parallel_training [FILE]`
+//!
+//! Each line of FILE is a string of the collection. Without a file, a synthetic
+//! column of URL-like strings is used.
+
+use onpair_rs::{OnPair16Dictionary, OnPairConfig, OnPairDictionary};
+use std::time::Instant;
+
+const THRESHOLD: u16 = 5;
+const SEED: u64 = 42;
+const BATCH_SIZES: [usize; 3] = [1 << 12, 1 << 14, 1 << 16];
+
+fn main() {
+    let strings = match std::env::args().nth(1) {
+        Some(path) => std::fs::read_to_string(path)
+            .expect("failed to read input file")
+            .lines()
+            .map(str::to_owned)
+            .collect(),
+        None => synthetic_strings(1_000_000),
+    };
+
+    let mut data = Vec::new();
+    let mut end_positions = vec![0];
+    for string in &strings {
+        data.extend_from_slice(string.as_bytes());
+        end_positions.push(data.len());
+    }
+
+    println!(
+        "{} strings, {} bytes, {} threads\n",
+        strings.len(),
+        data.len(),
+        rayon::current_num_threads()
+    );
+    println!("{:<10} {:<12} {:>10} {:>10} {:>8} {:>8}", "variant", "training", "train (s)", "parse (s)", "tokens", "ratio");
+
+    let sequential = OnPairConfig::new(THRESHOLD).with_seed(SEED);
+    let parallel = BATCH_SIZES.map(|batch_size| (format!("batch {}", batch_size), sequential.with_parallel_training(batch_size)));
+
+    for (name, config) in std::iter::once(("sequential".to_owned(), sequential)).chain(parallel) {
+        run::<OnPairDictionary>(&name, &data, &end_positions, &config);
+        run::<OnPair16Dictionary>(&name, &data, &end_positions, &config);
+    }
+}
+
+/// The dictionary operations timed by the example
+trait Dictionary: Sized {
+    const NAME: &'static str;
+
+    fn train_with_config(data: &[u8], end_positions: &[usize], config: &OnPairConfig) -> Self;
+
+    fn par_encode(&self, data: &[u8], end_positions: &[usize], compressed_data: &mut Vec<u16>, string_boundaries: &mut Vec<usize>);
+
+    fn num_tokens(&self) -> usize;
+
+    fn space_used(&self) -> usize;
+}
+
+macro_rules! impl_dictionary {
+    ($($ty:ty => $name:literal),*) => {$(
+        impl Dictionary for $ty {
+            const NAME: &'static str = $name;
+
+            fn train_with_config(data: &[u8], end_positions: &[usize], config: &OnPairConfig) -> Self {
+                <$ty>::train_with_config(data, end_positions, config)
+            }
+
+            fn par_encode(&self, data: &[u8], end_positions: &[usize], compressed_data: &mut Vec<u16>, string_boundaries: &mut Vec<usize>) {
+                <$ty>::par_encode(self, data, end_positions, compressed_data, string_boundaries)
+            }
+
+            fn num_tokens(&self) -> usize {
+                <$ty>::num_tokens(self)
+            }
+
+            fn space_used(&self) -> usize {
+                <$ty>::space_used(self)
+            }
+        }
+    )*};
+}
+
+impl_dictionary!(OnPairDictionary => "OnPair", OnPair16Dictionary => "OnPair16");
+
+/// Trains a dictionary with `config`, parses the strings with it and prints one row of timings
+fn run<D: Dictionary>(name: &str, data: &[u8], end_positions: &[usize], config: &OnPairConfig) {
+    let start = Instant::now();
+    let dictionary = D::train_with_config(data, end_positions, config);
+    let train_time = start.elapsed().as_secs_f64();
+
+    let (mut compressed_data, mut string_boundaries) = (Vec::new(), Vec::new());
+    let start = Instant::now();
+    dictionary.par_encode(data, end_positions, &mut compressed_data, &mut string_boundaries);
+    let parse_time = start.elapsed().as_secs_f64();
+
+    let compressed_size = compressed_data.len() * 2 + dictionary.space_used();
+    let ratio = data.len() as f64 / compressed_size as f64;
+    println!("{:<10} {:<12} {:>10.3} {:>10.3} {:>8} {:>8.3}", D::NAME, name, train_time, parse_time, dictionary.num_tokens(), ratio);
+}
+
+/// Generates URL-like strings with a skewed distribution of hosts and paths
+fn synthetic_strings(n: usize) -> Vec<String> {
+    const HOSTS: [&str; 6] = ["www.example.com", "api.example.org", "cdn.static.net", "shop.example.com", "blog.example.io", "mail.example.com"];
+    const PATHS: [&str; 5] = ["users", "products", "images", "articles", "orders"];
+
+    // Small linear congruential generator, so the example needs no extra dependency
+    let mut state = SEED;
+    let mut next = move || {
+        state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (state >> 33) as usize
+    };
+
+    (0..n)
+        .map(|_| {
+            let host = HOSTS[(next() % HOSTS.len()).min(next() % HOSTS.len())];
+            let path = PATHS[next() % PATHS.len()];
+            format!("https://{}/{}/{}?page={}", host, path, next() % 100_000, next() % 50)
+        })
+        .collect()
+}
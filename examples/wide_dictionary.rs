@@ -0,0 +1,144 @@
+//! Compares `OnPair` (16-bit token IDs) with `OnPair32` (32-bit token IDs)
+//!
+//! Usage: `cargo run --release --example wide_dictionary [FILE]`
+//!
+//! Each line of FILE is a string of the collection. Without a file, a synthetic
+//! column with 500,000 distinct URLs is used, on which a 65,536-token dictionary
+//! fills up early.
+
+use onpair_rs::{OnPair, OnPair32, OnPairConfig, StringCompressor};
+use std::time::Instant;
+
+const THRESHOLD: u16 = 5;
+const SEED: u64 = 42;
+const N_QUERIES: usize = 1_000_000;
+const MAX_TOKENS: [usize; 4] = [1 << 16, 1 << 18, 1 << 20, 1 << 24];
+
+fn main() {
+    let strings = match std::env::args().nth(1) {
+        Some(path) => std::fs::read_to_string(path)
+            .expect("failed to read input file")
+            .lines()
+            .map(str::to_owned)
+            .collect(),
+        None => synthetic_strings(2_000_000),
+    };
+
+    let mut data = Vec::new();
+    let mut end_positions = vec![0];
+    for string in &strings {
+        data.extend_from_slice(string.as_bytes());
+        end_positions.push(data.len());
+    }
+
+    println!("{} strings, {} bytes\n", strings.len(), data.len());
+    println!(
+        "{:<10} {:>10} {:>8} {:>10} {:>12} {:>14}",
+        "variant", "tokens", "ratio", "comp (s)", "access (ns)", "decomp (GB/s)"
+    );
+
+    let config = OnPairConfig::new(THRESHOLD).with_seed(SEED);
+
+    let (compressor, result) = benchmark(OnPair::with_config(config), &data, &end_positions);
+    result.print("OnPair", compressor.dictionary().num_tokens());
+
+    for max_tokens in MAX_TOKENS {
+        let config = config.with_max_tokens(max_tokens);
+        let (compressor, result) = benchmark(OnPair32::with_config(config), &data, &end_positions);
+        result.print("OnPair32", compressor.dictionary().num_tokens());
+    }
+}
+
+/// Measurements of a single variant
+struct BenchmarkResult {
+    ratio: f64,
+    compression_time: f64,
+    access_time: f64,
+    decompression_speed: f64,
+}
+
+impl BenchmarkResult {
+    fn print(&self, name: &str, n_tokens: usize) {
+        println!(
+            "{:<10} {:>10} {:>8.3} {:>10.3} {:>12.1} {:>14.2}",
+            name, n_tokens, self.ratio, self.compression_time, self.access_time, self.decompression_speed
+        );
+    }
+}
+
+/// Compresses the collection, then measures random access and full decompression
+fn benchmark<C: StringCompressor>(mut compressor: C, data: &[u8], end_positions: &[usize]) -> (C, BenchmarkResult) {
+    let n_strings = end_positions.len() - 1;
+
+    let start = Instant::now();
+    compressor.compress_bytes(data, end_positions);
+    let compression_time = start.elapsed().as_secs_f64();
+
+    let ratio = data.len() as f64 / compressor.space_used() as f64;
+
+    // Random access to single strings
+    let max_length = end_positions.windows(2).map(|w| w[1] - w[0]).max().unwrap_or(0);
+    let mut buffer = vec![0; max_length + 16];
+    let mut state = SEED;
+    let mut checksum = 0;
+    let start = Instant::now();
+    for _ in 0..N_QUERIES {
+        state = next(state);
+        let index = (state >> 33) as usize % n_strings;
+        checksum += unsafe { compressor.decompress_string(index, &mut buffer) };
+    }
+    let access_time = start.elapsed().as_nanos() as f64 / N_QUERIES as f64;
+    std::hint::black_box(checksum);
+
+    // Sequential decompression of the whole collection
+    let mut buffer = vec![0; data.len() + 16];
+    let start = Instant::now();
+    let size = unsafe { compressor.decompress_all(&mut buffer) };
+    let decompression_speed = size as f64 / start.elapsed().as_secs_f64() / 1e9;
+    assert_eq!(&buffer[..size], data);
+
+    let result = BenchmarkResult { ratio, compression_time, access_time, decompression_speed };
+    (compressor, result)
+}
+
+/// Generates a column with many distinct URL paths, drawn with a skewed distribution
+fn synthetic_strings(n: usize) -> Vec<String> {
+    const N_SEGMENTS: usize = 50_000;
+    const N_DISTINCT: usize = 500_000;
+
+    // Small linear congruential generator, so the example needs no extra dependency
+    let mut state = SEED;
+    let mut random = move || {
+        state = next(state);
+        (state >> 33) as usize
+    };
+
+    let segments: Vec<String> = (0..N_SEGMENTS)
+        .map(|_| {
+            let length = 4 + random() % 8;
+            (0..length).map(|_| (b'a' + (random() % 26) as u8) as char).collect()
+        })
+        .collect();
+
+    let paths: Vec<String> = (0..N_DISTINCT)
+        .map(|_| {
+            let depth = 2 + random() % 3;
+            let mut path = String::from("https://example.com");
+            for _ in 0..depth {
+                path.push('/');
+                path.push_str(&segments[random() % N_SEGMENTS]);
+            }
+            path
+        })
+        .collect();
+
+    // Minimum of two draws favours the first paths
+    (0..n)
+        .map(|_| paths[(random() % N_DISTINCT).min(random() % N_DISTINCT)].clone())
+        .collect()
+}
+
+/// Advances the linear congruential generator
+fn next(state: u64) -> u64 {
+    state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407)
+}
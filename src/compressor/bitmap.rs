@@ -0,0 +1,86 @@
+//! Bitmaps of matching strings
+//!
+//! The search methods of the compressors return the indices of the matching
+//! strings either as an iterator or as a `Bitmap` with one bit per string of the
+//! collection, which is convenient to combine the results of several predicates.
+
+/// Set of string indices of a collection, stored as one bit per string
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Bitmap {
+    words: Vec<u64>,    // Bits of the strings, least significant bit first
+    len: usize,         // Number of strings covered
+}
+
+impl Bitmap {
+    /// Creates a bitmap covering `len` strings, none of which is set
+    pub fn new(len: usize) -> Self {
+        Self { words: vec![0; len.div_ceil(64)], len }
+    }
+
+    /// Creates a bitmap covering `len` strings with the given indices set
+    pub fn from_indices<I: IntoIterator<Item = usize>>(len: usize, indices: I) -> Self {
+        let mut bitmap = Self::new(len);
+        for index in indices {
+            bitmap.insert(index);
+        }
+        bitmap
+    }
+
+    /// Returns the number of strings covered by the bitmap
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the bitmap covers no strings
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Sets the bit of string `index`
+    ///
+    /// # Panics
+    /// Panics if `index` is not smaller than `len()`.
+    #[inline]
+    pub fn insert(&mut self, index: usize) {
+        assert!(index < self.len, "Index {} out of bounds for a bitmap of {} strings", index, self.len);
+        self.words[index / 64] |= 1 << (index % 64);
+    }
+
+    /// Returns `true` if the bit of string `index` is set
+    ///
+    /// # Panics
+    /// Panics if `index` is not smaller than `len()`.
+    #[inline]
+    pub fn contains(&self, index: usize) -> bool {
+        assert!(index < self.len, "Index {} out of bounds for a bitmap of {} strings", index, self.len);
+        self.words[index / 64] & (1 << (index % 64)) != 0
+    }
+
+    /// Returns the number of strings set
+    pub fn count_ones(&self) -> usize {
+        self.words.iter().map(|word| word.count_ones() as usize).sum()
+    }
+
+    /// Returns an iterator over the indices of the strings set, in increasing order
+    pub fn iter_ones(&self) -> impl Iterator<Item = usize> + '_ {
+        self.words.iter().enumerate().flat_map(|(position, &word)| {
+            let mut word = word;
+            std::iter::from_fn(move || {
+                if word == 0 {
+                    return None;
+                }
+                let bit = word.trailing_zeros() as usize;
+                word &= word - 1;
+                Some(position * 64 + bit)
+            })
+        })
+    }
+
+    /// Returns the bits as 64-bit words, string `i` being bit `i % 64` of word `i / 64`
+    #[inline]
+    pub fn as_words(&self) -> &[u64] {
+        &self.words
+    }
+}
@@ -0,0 +1,227 @@
+//! Compact storage for string boundaries
+//!
+//! The owned compressors keep one end position in the token stream per string.
+//! Positions are stored as `u32` while they fit, which halves the boundary array
+//! for any collection of fewer than 2^32 tokens, and switch to `u64` once a
+//! position exceeds `u32::MAX`. Either way, every position is read in O(1).
+
+use std::ops::Range;
+
+/// End positions of the strings in the token stream, starting with 0
+#[derive(Clone, Debug)]
+pub(crate) enum StringBoundaries {
+    Narrow(Vec<u32>),
+    Wide(Vec<u64>),
+}
+
+impl StringBoundaries {
+    /// Creates an empty array of narrow positions
+    pub(crate) fn new() -> Self {
+        Self::Narrow(Vec::new())
+    }
+
+    /// Returns the number of positions, i.e. the number of strings plus one
+    #[inline]
+    pub(crate) fn len(&self) -> usize {
+        match self {
+            Self::Narrow(positions) => positions.len(),
+            Self::Wide(positions) => positions.len(),
+        }
+    }
+
+    /// Returns `true` if no position is stored
+    #[inline]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the position at `index`
+    #[inline(always)]
+    pub(crate) fn get(&self, index: usize) -> usize {
+        match self {
+            Self::Narrow(positions) => positions[index] as usize,
+            Self::Wide(positions) => positions[index] as usize,
+        }
+    }
+
+    /// Returns the range of token positions of string `index`
+    #[inline(always)]
+    pub(crate) fn range(&self, index: usize) -> Range<usize> {
+        self.get(index)..self.get(index + 1)
+    }
+
+    /// Appends a position, switching to wide positions if it does not fit in `u32`
+    #[inline]
+    pub(crate) fn push(&mut self, position: usize) {
+        match self {
+            Self::Narrow(positions) => match u32::try_from(position) {
+                Ok(position) => positions.push(position),
+                Err(_) => {
+                    self.widen();
+                    self.push(position);
+                }
+            },
+            Self::Wide(positions) => positions.push(position as u64),
+        }
+    }
+
+    /// Appends positions, each shifted by `offset`
+    pub(crate) fn extend_shifted(&mut self, positions: &[usize], offset: usize) {
+        self.reserve(positions.len());
+        for &position in positions {
+            self.push(position + offset);
+        }
+    }
+
+    /// Returns an iterator over the positions
+    pub(crate) fn iter(&self) -> impl ExactSizeIterator<Item = usize> + '_ {
+        (0..self.len()).map(move |index| self.get(index))
+    }
+
+    /// Removes every position, going back to narrow positions
+    pub(crate) fn clear(&mut self) {
+        match self {
+            Self::Narrow(positions) => positions.clear(),
+            Self::Wide(_) => *self = Self::new(),
+        }
+    }
+
+    /// Reserves room for at least `additional` more positions
+    pub(crate) fn reserve(&mut self, additional: usize) {
+        match self {
+            Self::Narrow(positions) => positions.reserve(additional),
+            Self::Wide(positions) => positions.reserve(additional),
+        }
+    }
+
+    /// Returns the space (in bytes) used by the positions
+    pub(crate) fn space_used(&self) -> usize {
+        match self {
+            Self::Narrow(positions) => positions.len() * std::mem::size_of::<u32>(),
+            Self::Wide(positions) => positions.len() * std::mem::size_of::<u64>(),
+        }
+    }
+
+    /// Returns the heap memory (in bytes) allocated for the positions, including spare capacity
+    pub(crate) fn heap_size(&self) -> usize {
+        match self {
+            Self::Narrow(positions) => positions.capacity() * std::mem::size_of::<u32>(),
+            Self::Wide(positions) => positions.capacity() * std::mem::size_of::<u64>(),
+        }
+    }
+
+    /// Shrinks the storage to fit its current contents
+    pub(crate) fn shrink_to_fit(&mut self) {
+        match self {
+            Self::Narrow(positions) => positions.shrink_to_fit(),
+            Self::Wide(positions) => positions.shrink_to_fit(),
+        }
+    }
+
+    /// Converts narrow positions to wide ones
+    fn widen(&mut self) {
+        if let Self::Narrow(positions) = self {
+            let mut wide = Vec::with_capacity(positions.capacity());
+            wide.extend(positions.iter().map(|&position| position as u64));
+            *self = Self::Wide(wide);
+        }
+    }
+}
+
+impl From<Vec<usize>> for StringBoundaries {
+    /// Stores the positions narrow if the last one fits in `u32`
+    fn from(positions: Vec<usize>) -> Self {
+        match positions.last() {
+            Some(&last) if u32::try_from(last).is_err() => {
+                Self::Wide(positions.into_iter().map(|position| position as u64).collect())
+            }
+            _ => Self::Narrow(positions.into_iter().map(|position| position as u32).collect()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LARGE: usize = u32::MAX as usize;
+
+    fn check(boundaries: &StringBoundaries, expected: &[usize]) {
+        assert_eq!(boundaries.len(), expected.len());
+        assert!(boundaries.iter().eq(expected.iter().copied()));
+        for (index, &position) in expected.iter().enumerate() {
+            assert_eq!(boundaries.get(index), position);
+        }
+        for index in 0..expected.len().saturating_sub(1) {
+            assert_eq!(boundaries.range(index), expected[index]..expected[index + 1]);
+        }
+        assert!(boundaries.heap_size() >= boundaries.space_used());
+    }
+
+    #[test]
+    fn narrow_positions() {
+        let mut boundaries = StringBoundaries::new();
+        assert!(boundaries.is_empty());
+        boundaries.push(0);
+        boundaries.extend_shifted(&[3, 5, 5], 10);
+        boundaries.push(LARGE);
+        assert!(matches!(boundaries, StringBoundaries::Narrow(_)));
+        check(&boundaries, &[0, 13, 15, 15, LARGE]);
+        assert_eq!(boundaries.space_used(), 5 * 4);
+    }
+
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn push_beyond_u32_widens() {
+        let expected = [0, 7, LARGE - 1, LARGE + 1, LARGE + 2];
+
+        let mut boundaries = StringBoundaries::new();
+        boundaries.extend_shifted(&expected[..3], 0);
+        assert!(matches!(boundaries, StringBoundaries::Narrow(_)));
+        check(&boundaries, &expected[..3]);
+        let narrow_size = boundaries.space_used();
+
+        boundaries.push(LARGE + 1);
+        assert!(matches!(boundaries, StringBoundaries::Wide(_)));
+        check(&boundaries, &expected[..4]);
+        assert_eq!(boundaries.space_used(), 4 * 8);
+        assert!(boundaries.space_used() > narrow_size);
+        assert!(boundaries.heap_size() >= 4 * 8);
+
+        // Shifted positions beyond u32 stay wide, and earlier positions are unchanged
+        boundaries.extend_shifted(&[1], LARGE + 1);
+        check(&boundaries, &expected);
+
+        let heap_size = boundaries.heap_size();
+        boundaries.shrink_to_fit();
+        assert!(boundaries.heap_size() <= heap_size);
+        check(&boundaries, &expected);
+
+        boundaries.clear();
+        assert!(matches!(boundaries, StringBoundaries::Narrow(_)));
+        assert!(boundaries.is_empty());
+    }
+
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn extend_shifted_widens() {
+        let mut boundaries = StringBoundaries::new();
+        boundaries.extend_shifted(&[0, 2, 4], LARGE - 2);
+        assert!(matches!(boundaries, StringBoundaries::Wide(_)));
+        check(&boundaries, &[LARGE - 2, LARGE, LARGE + 2]);
+    }
+
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn from_positions() {
+        let narrow = StringBoundaries::from(vec![0, 4, LARGE]);
+        assert!(matches!(narrow, StringBoundaries::Narrow(_)));
+        check(&narrow, &[0, 4, LARGE]);
+
+        let wide = StringBoundaries::from(vec![0, 4, LARGE + 1]);
+        assert!(matches!(wide, StringBoundaries::Wide(_)));
+        check(&wide, &[0, 4, LARGE + 1]);
+
+        assert!(matches!(StringBoundaries::from(Vec::new()), StringBoundaries::Narrow(_)));
+    }
+}
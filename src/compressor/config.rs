@@ -0,0 +1,351 @@
+//! Training configuration shared by the OnPair compressors
+
+use crate::compressor::sample::{self, Sample, SampleStrategy};
+use crate::compressor::shuffle::Shuffle;
+use crate::error::{self, OnPairError};
+
+/// Maximum number of tokens addressable with 16-bit token IDs
+pub(crate) const MAX_TOKENS_U16: usize = 1 << 16;
+
+/// Maximum number of tokens of an `OnPair32` dictionary
+pub(crate) const MAX_TOKENS_U32: usize = 1 << 24;
+
+/// Number of single-byte tokens every dictionary starts with
+pub(crate) const N_BYTE_TOKENS: usize = 256;
+
+/// Training parameters for `OnPair`, `OnPair16` and `OnPair32`
+///
+/// Built from a merging threshold, with every other knob optional:
+///
+/// ```
+/// use onpair_rs::{OnPair, OnPairConfig};
+///
+/// let config = OnPairConfig::new(5)
+///     .with_max_tokens(4096)
+///     .with_max_token_length(32)
+///     .with_seed(42)
+///     .with_sample_fraction(0.1);
+///
+/// let compressor = OnPair::with_config(config);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OnPairConfig {
+    pub(crate) threshold: u16,
+    pub(crate) max_tokens: Option<usize>,
+    pub(crate) max_token_length: Option<usize>,
+    pub(crate) shuffle: Shuffle,
+    pub(crate) sample: Sample,
+    pub(crate) sample_strategy: SampleStrategy,
+    pub(crate) parallel_batch: Option<usize>,
+}
+
+impl OnPairConfig {
+    /// Creates a configuration with the given merging frequency threshold
+    ///
+    /// Defaults: the variant's maximum number of tokens (65,536, or 16,777,216 for
+    /// `OnPair32`), the variant's maximum token length (unbounded, or 16 bytes for
+    /// `OnPair16`), random shuffling, and training on every string.
+    pub fn new(threshold: u16) -> Self {
+        Self {
+            threshold,
+            max_tokens: None,
+            max_token_length: None,
+            shuffle: Shuffle::Random,
+            sample: Sample::All,
+            sample_strategy: SampleStrategy::Random,
+            parallel_batch: None,
+        }
+    }
+
+    /// Sets the merging frequency threshold (must be greater than 1)
+    pub fn with_threshold(mut self, threshold: u16) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Sets the maximum number of tokens, including the 256 single-byte tokens
+    ///
+    /// Must be between 256 and 65,536, or 16,777,216 for `OnPair32`.
+    pub fn with_max_tokens(mut self, max_tokens: usize) -> Self {
+        self.max_tokens = Some(max_tokens);
+        self
+    }
+
+    /// Sets the maximum length in bytes of a dictionary token
+    ///
+    /// Must be at least 1, and at most 16 for `OnPair16`.
+    pub fn with_max_token_length(mut self, max_token_length: usize) -> Self {
+        self.max_token_length = Some(max_token_length);
+        self
+    }
+
+    /// Seeds the training shuffle, making compression reproducible
+    ///
+    /// Shorthand for `with_shuffle(Shuffle::Seeded(seed))`.
+    pub fn with_seed(self, seed: u64) -> Self {
+        self.with_shuffle(Shuffle::Seeded(seed))
+    }
+
+    /// Sets the order in which training visits the strings
+    pub fn with_shuffle(mut self, shuffle: Shuffle) -> Self {
+        self.shuffle = shuffle;
+        self
+    }
+
+    /// Sets how much of the input is used for training
+    ///
+    /// Only the dictionary is built from the sample: all strings are still compressed.
+    pub fn with_sample(mut self, sample: Sample) -> Self {
+        self.sample = sample;
+        self
+    }
+
+    /// Trains on the given fraction of the strings, in `(0, 1]`
+    ///
+    /// Shorthand for `with_sample(Sample::Fraction(sample_fraction))`.
+    pub fn with_sample_fraction(self, sample_fraction: f64) -> Self {
+        self.with_sample(Sample::Fraction(sample_fraction))
+    }
+
+    /// Trains on at most the given number of strings
+    ///
+    /// Shorthand for `with_sample(Sample::Count(sample_count))`.
+    pub fn with_sample_count(self, sample_count: usize) -> Self {
+        self.with_sample(Sample::Count(sample_count))
+    }
+
+    /// Trains on strings until at least the given number of bytes is selected
+    ///
+    /// Shorthand for `with_sample(Sample::Bytes(sample_bytes))`.
+    pub fn with_sample_bytes(self, sample_bytes: usize) -> Self {
+        self.with_sample(Sample::Bytes(sample_bytes))
+    }
+
+    /// Sets how the sampled strings are spread over the input
+    pub fn with_sample_strategy(mut self, sample_strategy: SampleStrategy) -> Self {
+        self.sample_strategy = sample_strategy;
+        self
+    }
+
+    /// Trains on multiple threads, merging pair counts after every batch of `batch_size` strings
+    ///
+    /// Workers count token pairs on disjoint shards of each batch against a shared snapshot
+    /// of the dictionary, and the pairs that reached the threshold are merged once the batch
+    /// is done. Tokens created within a batch therefore only take effect from the next one,
+    /// so the dictionary differs from the one built by sequential training. Smaller batches
+    /// follow the sequential algorithm more closely, larger ones parallelize better; a few
+    /// thousand strings per thread is a reasonable starting point.
+    ///
+    /// The result depends on the batch size but not on the number of threads, so a seeded
+    /// configuration remains reproducible.
+    #[cfg(feature = "parallel")]
+    pub fn with_parallel_training(mut self, batch_size: usize) -> Self {
+        self.parallel_batch = Some(batch_size);
+        self
+    }
+
+    /// Returns the merging frequency threshold
+    #[inline]
+    pub fn threshold(&self) -> u16 {
+        self.threshold
+    }
+
+    /// Returns the maximum number of tokens, or `None` for the variant's maximum
+    #[inline]
+    pub fn max_tokens(&self) -> Option<usize> {
+        self.max_tokens
+    }
+
+    /// Returns the maximum token length, or `None` for the variant's maximum
+    #[inline]
+    pub fn max_token_length(&self) -> Option<usize> {
+        self.max_token_length
+    }
+
+    /// Returns the shuffle seed, or `None` unless the shuffle is [`Shuffle::Seeded`]
+    #[inline]
+    pub fn seed(&self) -> Option<u64> {
+        match self.shuffle {
+            Shuffle::Seeded(seed) => Some(seed),
+            _ => None,
+        }
+    }
+
+    /// Returns the order in which training visits the strings
+    #[inline]
+    pub fn shuffle(&self) -> Shuffle {
+        self.shuffle
+    }
+
+    /// Returns how much of the input is used for training
+    #[inline]
+    pub fn sample(&self) -> Sample {
+        self.sample
+    }
+
+    /// Returns how the sampled strings are spread over the input
+    #[inline]
+    pub fn sample_strategy(&self) -> SampleStrategy {
+        self.sample_strategy
+    }
+
+    /// Returns the batch size of parallel training, or `None` for sequential training
+    #[cfg(feature = "parallel")]
+    #[inline]
+    pub fn parallel_training(&self) -> Option<usize> {
+        self.parallel_batch
+    }
+
+    /// Checks the configuration for a variant whose tokens are at most `length_limit` bytes
+    /// and whose dictionaries hold at most `token_limit` tokens
+    pub(crate) fn validate(&self, length_limit: usize, token_limit: usize) -> Result<(), OnPairError> {
+        error::check_threshold(self.threshold)?;
+
+        if let Some(max_tokens) = self.max_tokens {
+            if !(N_BYTE_TOKENS..=token_limit).contains(&max_tokens) {
+                return Err(OnPairError::InvalidConfig("max_tokens is out of range for this variant"));
+            }
+        }
+        if let Some(max_token_length) = self.max_token_length {
+            if max_token_length == 0 || max_token_length > length_limit {
+                return Err(OnPairError::InvalidConfig("max_token_length is out of range for this variant"));
+            }
+        }
+        if self.parallel_batch == Some(0) {
+            return Err(OnPairError::InvalidConfig("parallel training batch size must be positive"));
+        }
+        if !self.sample.is_valid() {
+            return Err(OnPairError::InvalidConfig("sample size must be positive, and fractions at most 1"));
+        }
+
+        Ok(())
+    }
+
+    /// Returns the effective maximum token length for a variant limited to `length_limit` bytes
+    #[inline]
+    pub(crate) fn token_length_limit(&self, length_limit: usize) -> usize {
+        self.max_token_length.unwrap_or(length_limit).min(length_limit)
+    }
+
+    /// Returns the effective maximum number of tokens for a variant limited to `token_limit` tokens
+    #[inline]
+    pub(crate) fn token_count_limit(&self, token_limit: usize) -> usize {
+        self.max_tokens.unwrap_or(token_limit).min(token_limit)
+    }
+
+    /// Returns the strings to train on, in training order
+    pub(crate) fn training_order(&self, end_positions: &[usize]) -> Vec<usize> {
+        let order = self.shuffle.order(end_positions.len().saturating_sub(1));
+        self.sample_order(order, end_positions)
+    }
+
+    /// Restricts a training order over all strings to the configured sample
+    pub(crate) fn sample_order(&self, order: Vec<usize>, end_positions: &[usize]) -> Vec<usize> {
+        sample::select(order, end_positions, self.sample, self.sample_strategy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compressor::{OnPair, OnPair16, OnPair32, OnPairPacked};
+
+    /// Checks `config` against every variant, which accept it as listed
+    fn check(config: OnPairConfig, onpair: bool, onpair16: bool, onpair32: bool) {
+        assert_eq!(OnPair::try_with_config(config).is_ok(), onpair, "OnPair with {:?}", config);
+        assert_eq!(OnPairPacked::try_with_config(config).is_ok(), onpair, "OnPairPacked with {:?}", config);
+        assert_eq!(OnPair16::try_with_config(config).is_ok(), onpair16, "OnPair16 with {:?}", config);
+        assert_eq!(OnPair32::try_with_config(config).is_ok(), onpair32, "OnPair32 with {:?}", config);
+    }
+
+    #[test]
+    fn validate_threshold() {
+        check(OnPairConfig::new(2), true, true, true);
+        check(OnPairConfig::new(0), false, false, false);
+        check(OnPairConfig::new(1), false, false, false);
+        check(OnPairConfig::new(5).with_threshold(1), false, false, false);
+    }
+
+    #[test]
+    fn validate_max_token_length() {
+        let config = OnPairConfig::new(3);
+        check(config.with_max_token_length(0), false, false, false);
+        check(config.with_max_token_length(1), true, true, true);
+        check(config.with_max_token_length(16), true, true, true);
+        check(config.with_max_token_length(17), true, false, true);
+        check(config.with_max_token_length(usize::MAX), true, false, true);
+    }
+
+    #[test]
+    fn validate_max_tokens() {
+        let config = OnPairConfig::new(3);
+        check(config.with_max_tokens(0), false, false, false);
+        check(config.with_max_tokens(N_BYTE_TOKENS - 1), false, false, false);
+        check(config.with_max_tokens(N_BYTE_TOKENS), true, true, true);
+        check(config.with_max_tokens(MAX_TOKENS_U16), true, true, true);
+        check(config.with_max_tokens(MAX_TOKENS_U16 + 1), false, false, true);
+        check(config.with_max_tokens(MAX_TOKENS_U32), false, false, true);
+        check(config.with_max_tokens(MAX_TOKENS_U32 + 1), false, false, false);
+    }
+
+    #[test]
+    fn validate_sample() {
+        let config = OnPairConfig::new(3);
+        check(config.with_sample_fraction(1.0), true, true, true);
+        check(config.with_sample_fraction(0.01), true, true, true);
+        check(config.with_sample_fraction(0.0), false, false, false);
+        check(config.with_sample_fraction(-0.5), false, false, false);
+        check(config.with_sample_fraction(1.01), false, false, false);
+        check(config.with_sample_fraction(f64::NAN), false, false, false);
+        check(config.with_sample_count(0), false, false, false);
+        check(config.with_sample_bytes(0), false, false, false);
+        check(config.with_sample_count(1).with_sample_strategy(SampleStrategy::Stratified), true, true, true);
+    }
+
+    #[test]
+    fn validate_reports_the_invalid_setting() {
+        let config = OnPairConfig::new(3).with_max_token_length(17);
+        assert!(matches!(OnPair16::try_with_config(config), Err(OnPairError::InvalidConfig(reason)) if reason.contains("max_token_length")));
+        let config = OnPairConfig::new(3).with_max_tokens(100);
+        assert!(matches!(OnPair::try_with_config(config), Err(OnPairError::InvalidConfig(reason)) if reason.contains("max_tokens")));
+        let config = OnPairConfig::new(3).with_sample_fraction(2.0);
+        assert!(matches!(OnPair::try_with_config(config), Err(OnPairError::InvalidConfig(reason)) if reason.contains("sample")));
+    }
+
+    #[test]
+    #[cfg(feature = "parallel")]
+    fn validate_parallel_training() {
+        let config = OnPairConfig::new(3);
+        check(config.with_parallel_training(1), true, true, true);
+        check(config.with_parallel_training(0), false, false, false);
+    }
+
+    #[test]
+    fn getters_return_the_builder_settings() {
+        let config = OnPairConfig::new(3);
+        assert_eq!(config.threshold(), 3);
+        assert_eq!((config.max_tokens(), config.max_token_length(), config.seed()), (None, None, None));
+        assert_eq!(config.shuffle(), Shuffle::Random);
+        assert_eq!(config.sample(), Sample::All);
+        assert_eq!(config.sample_strategy(), SampleStrategy::Random);
+
+        let config = config
+            .with_threshold(7)
+            .with_max_tokens(1000)
+            .with_max_token_length(12)
+            .with_seed(42)
+            .with_sample_count(10)
+            .with_sample_strategy(SampleStrategy::Stratified);
+        assert_eq!(config.threshold(), 7);
+        assert_eq!((config.max_tokens(), config.max_token_length(), config.seed()), (Some(1000), Some(12), Some(42)));
+        assert_eq!(config.shuffle(), Shuffle::Seeded(42));
+        assert_eq!(config.sample(), Sample::Count(10));
+        assert_eq!(config.sample_strategy(), SampleStrategy::Stratified);
+        assert_eq!(config.with_shuffle(Shuffle::Disabled).seed(), None);
+        #[cfg(feature = "parallel")]
+        {
+            assert_eq!(config.parallel_training(), None);
+            assert_eq!(config.with_parallel_training(512).parallel_training(), Some(512));
+        }
+    }
+}
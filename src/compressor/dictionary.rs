@@ -0,0 +1,391 @@
+//! Standalone dictionaries for the OnPair and OnPair32 compressors
+//!
+//! An `OnPairDictionary` owns the token bytes, the token boundaries and the
+//! matcher used to parse strings. It can be trained once on a sample and then
+//! used to encode any number of independent collections or partitions.
+//! Training uses the dynamic `LongestPrefixMatcher`, which is then finalized
+//! into a `StaticLongestPrefixMatcher` used for encoding.
+//!
+//! `OnPair32Dictionary` is the same dictionary with 32-bit token IDs, so that it
+//! can keep growing past 65,536 tokens on high-cardinality data. Both are aliases
+//! of `OnPairDictionaryBase`, which is generic over the token ID type.
+
+use crate::compressor::config::{OnPairConfig, N_BYTE_TOKENS};
+#[cfg(feature = "parallel")]
+use crate::compressor::parallel;
+use crate::compressor::sample::TrainingReport;
+use crate::compressor::shuffle;
+use crate::compressor::token::TokenId;
+use crate::lpm::{hash_map_heap_size, LongestPrefixMatcher, StaticLongestPrefixMatcher};
+use rustc_hash::FxHashMap;
+use rand::Rng;
+use std::sync::OnceLock;
+
+/// Number of zero bytes kept after the token data, so that fast-copy decoding of the
+/// last token never reads past the allocation
+pub(crate) const DICTIONARY_PADDING: usize = 16;
+
+/// Trained OnPair dictionary (up to 65,536 tokens of arbitrary length)
+pub type OnPairDictionary = OnPairDictionaryBase<u16>;
+
+/// Trained OnPair32 dictionary (up to 16,777,216 tokens of arbitrary length)
+pub type OnPair32Dictionary = OnPairDictionaryBase<u32>;
+
+/// Trained OnPair dictionary with token IDs of type `T`
+pub struct OnPairDictionaryBase<T: TokenId> {
+    // Merging frequency threshold used for training
+    pub(crate) threshold: u16,
+
+    // Dictionary storage
+    pub(crate) bytes: Vec<u8>,                      // Raw token data, followed by zero padding
+    pub(crate) token_boundaries: Vec<u32>,          // Token end positions in dictionary
+
+    // Strings visited by training, unknown for dictionaries not trained in this process
+    training_report: Option<TrainingReport>,
+
+    // Finalized matcher over the dictionary tokens, rebuilt on demand when missing
+    lpm: OnceLock<StaticLongestPrefixMatcher<T>>,
+}
+
+impl<T: TokenId> OnPairDictionaryBase<T> {
+    /// Trains a dictionary on pre-flattened byte data with end positions
+    ///
+    /// The `end_positions` should be a prefix sum array starting with 0.
+    ///
+    /// # Algorithm
+    /// 1. Initialize 256 single-byte tokens
+    /// 2. Parse shuffled training data (see [`Shuffle`](crate::Shuffle)) with longest prefix matching
+    /// 3. Track adjacent token pair frequencies, skipping pairs longer than the maximum token length
+    /// 4. Merge frequent pairs into new tokens until dictionary full (by default 65,536 tokens,
+    ///    or 16,777,216 for `OnPair32Dictionary`)
+    pub fn train(data: &[u8], end_positions: &[usize], threshold: u16) -> Self {
+        Self::train_with_config(data, end_positions, &OnPairConfig::new(threshold))
+    }
+
+    /// Trains a dictionary with the given configuration
+    ///
+    /// With `Shuffle::Seeded` or `Shuffle::Disabled`, identical input always yields
+    /// an identical dictionary.
+    ///
+    /// # Panics
+    /// Panics if the configuration is invalid for this variant.
+    pub fn train_with_config(data: &[u8], end_positions: &[usize], config: &OnPairConfig) -> Self {
+        let order = config.training_order(end_positions);
+        Self::train_in_order(data, end_positions, config, &order)
+    }
+
+    /// Trains a dictionary, shuffling the strings with the given random number generator
+    ///
+    /// The shuffling mode of `config` is ignored.
+    pub fn train_with_rng<R: Rng + ?Sized>(
+        data: &[u8],
+        end_positions: &[usize],
+        config: &OnPairConfig,
+        rng: &mut R,
+    ) -> Self {
+        let order = shuffle::shuffled_order(end_positions.len().saturating_sub(1), rng);
+        let order = config.sample_order(order, end_positions);
+        Self::train_in_order(data, end_positions, config, &order)
+    }
+
+    /// Trains a dictionary, visiting the strings in the given order
+    fn train_in_order(data: &[u8], end_positions: &[usize], config: &OnPairConfig, order: &[usize]) -> Self {
+        if let Err(error) = config.validate(usize::MAX, T::MAX_TOKENS) {
+            panic!("Invalid configuration: {}", error);
+        }
+        let threshold = config.threshold;
+        let max_length = config.token_length_limit(usize::MAX);
+        let max_tokens = config.token_count_limit(T::MAX_TOKENS);
+
+        let mut report = TrainingReport {
+            sampled_strings: order.len(),
+            sampled_bytes: order.iter().map(|&index| end_positions[index + 1] - end_positions[index]).sum(),
+            ..TrainingReport::default()
+        };
+
+        let order_size = std::mem::size_of_val(order);
+
+        // A dictionary limited to the single-byte tokens needs no training pass
+        let order = if max_tokens > N_BYTE_TOKENS { order } else { &[] };
+
+        let mut bytes = Vec::with_capacity(1024 * 1024);
+        let mut token_boundaries = Vec::with_capacity(1 << 16);
+        token_boundaries.push(0);
+
+        let mut frequency: FxHashMap<(T, T), u16> = FxHashMap::default();
+        let mut lpm = LongestPrefixMatcher::new();
+        let mut next_token_id = N_BYTE_TOKENS;
+
+        // Initialize the dictionary with single-byte tokens
+        for i in 0..256 {
+            let token = vec![i as u8];
+            lpm.insert(&token, T::from_index(i));
+            bytes.extend(&token);
+            token_boundaries.push(bytes.len() as u32);
+        }
+
+        // Parallel training visits the whole order in batches, leaving nothing for the loop below
+        #[cfg(feature = "parallel")]
+        let order = match config.parallel_batch {
+            Some(_) => {
+                let tokens = parallel::Tokens { lpm: &mut lpm, bytes: &mut bytes, token_boundaries: &mut token_boundaries };
+                let limits = parallel::Limits { max_length, max_tokens };
+                parallel::train(data, end_positions, order, config, limits, tokens, &mut report);
+                &[]
+            }
+            None => order,
+        };
+
+        // Iterate over entries
+        'outer: for &index in order {
+            let start = end_positions[index];
+            let end = end_positions[index + 1];
+
+            report.trained_strings += 1;
+            report.trained_bytes += end - start;
+
+            if start == end {
+                continue;
+            }
+
+            let (match_token_id, match_length) = lpm
+                .find_longest_match(&data[start..end])
+                .expect("single-byte tokens always match");
+            let mut previous_token_id = match_token_id;
+            let mut previous_length = match_length;
+
+            let mut pos = start + previous_length;
+
+            while pos < end {
+                // Find the longest match
+                let (match_token_id, match_length) = lpm
+                    .find_longest_match(&data[pos..end])
+                    .expect("single-byte tokens always match");
+
+                let mut added_token = false;
+                if match_length + previous_length <= max_length {
+                    // Update token frequency and possibly merge tokens
+                    *frequency.entry((previous_token_id, match_token_id)).or_insert(0) += 1;
+
+                    if frequency[&(previous_token_id, match_token_id)] >= threshold {
+                        let merged_token = &data[pos - previous_length..pos + match_length];
+
+                        // Token boundaries are 32-bit offsets, which caps the size of the token data
+                        if bytes.len() + merged_token.len() > u32::MAX as usize {
+                            report.dictionary_full = true;
+                            break 'outer;
+                        }
+
+                        let merged_token_id = T::from_index(next_token_id);
+                        lpm.insert(merged_token, merged_token_id);
+                        bytes.extend(merged_token);
+                        token_boundaries.push(bytes.len() as u32);
+
+                        frequency.remove(&(previous_token_id, match_token_id));
+                        previous_token_id = merged_token_id;
+                        previous_length = merged_token.len();
+                        added_token = true;
+
+                        if next_token_id + 1 == max_tokens {
+                            report.dictionary_full = true;
+                            break 'outer;
+                        }
+
+                        next_token_id += 1;
+                    }
+                }
+
+                if !added_token {
+                    previous_token_id = match_token_id;
+                    previous_length = match_length;
+                }
+
+                pos += match_length;
+            }
+        }
+
+        bytes.resize(bytes.len() + DICTIONARY_PADDING, 0);
+
+        // Training memory only grows, so it peaks once the finalized matcher sits next to the dynamic one
+        let static_lpm = lpm.finalize();
+        let memory = hash_map_heap_size(&frequency) +
+            lpm.heap_size() +
+            static_lpm.heap_size() +
+            bytes.capacity() +
+            token_boundaries.capacity() * std::mem::size_of::<u32>();
+        report.peak_memory = order_size + report.peak_memory.max(memory);
+
+        Self {
+            threshold,
+            bytes,
+            token_boundaries,
+            training_report: Some(report),
+            lpm: OnceLock::from(static_lpm),
+        }
+    }
+
+    /// Creates a dictionary from its raw parts; the matcher is rebuilt on first use
+    pub(crate) fn from_parts(threshold: u16, mut bytes: Vec<u8>, token_boundaries: Vec<u32>) -> Self {
+        bytes.resize(bytes.len() + DICTIONARY_PADDING, 0);
+
+        Self {
+            threshold,
+            bytes,
+            token_boundaries,
+            training_report: None,
+            lpm: OnceLock::new(),
+        }
+    }
+
+    /// Encodes strings by greedily applying longest prefix matching with the dictionary
+    ///
+    /// Appends the token IDs of each string to `compressed_data` and the end position of each
+    /// string to `string_boundaries`, which receives a leading 0 if it is empty.
+    ///
+    /// # Panics
+    /// Panics if the dictionary has not been trained.
+    pub fn encode(
+        &self,
+        data: &[u8],
+        end_positions: &[usize],
+        compressed_data: &mut Vec<T>,
+        string_boundaries: &mut Vec<usize>,
+    ) {
+        let lpm = self.matcher();
+
+        if string_boundaries.is_empty() {
+            string_boundaries.push(compressed_data.len());
+        }
+
+        for window in end_positions.windows(2) {
+            let start = window[0];
+            let end = window[1];
+
+            if start == end {
+                string_boundaries.push(compressed_data.len());
+                continue;
+            }
+
+            let mut pos = start;
+            while pos < end {
+                // Find the longest match
+                let (token_id, length) = lpm
+                    .find_longest_match(&data[pos..end])
+                    .expect("single-byte tokens always match");
+                compressed_data.push(token_id);
+                pos += length;
+            }
+
+            string_boundaries.push(compressed_data.len());
+        }
+    }
+
+    /// Returns the merging frequency threshold the dictionary was trained with
+    #[inline]
+    pub fn threshold(&self) -> u16 {
+        self.threshold
+    }
+
+    /// Encodes strings like [`encode`](Self::encode), parsing chunks of strings on multiple threads
+    ///
+    /// The output is identical to that of `encode`. Runs on the current rayon thread pool.
+    ///
+    /// # Panics
+    /// Panics if the dictionary has not been trained.
+    #[cfg(feature = "parallel")]
+    pub fn par_encode(
+        &self,
+        data: &[u8],
+        end_positions: &[usize],
+        compressed_data: &mut Vec<T>,
+        string_boundaries: &mut Vec<usize>,
+    ) {
+        // Build the matcher once, before the workers need it
+        self.matcher();
+
+        parallel::encode(end_positions, compressed_data, string_boundaries, |chunk, chunk_data, chunk_boundaries| {
+            self.encode(data, chunk, chunk_data, chunk_boundaries)
+        });
+    }
+
+    /// Returns which strings training visited, or `None` if the dictionary was not trained
+    /// in this process (e.g. it was deserialized)
+    #[inline]
+    pub fn training_report(&self) -> Option<TrainingReport> {
+        self.training_report
+    }
+
+    /// Returns the number of tokens in the dictionary
+    #[inline]
+    pub fn num_tokens(&self) -> usize {
+        self.token_boundaries.len().saturating_sub(1)
+    }
+
+    /// Returns the bytes of the token with the given ID
+    ///
+    /// # Panics
+    /// Panics if `id` is not smaller than `num_tokens()`.
+    #[inline]
+    pub fn token_bytes(&self, id: T) -> &[u8] {
+        let id = id.index();
+        &self.bytes[self.token_boundaries[id] as usize..self.token_boundaries[id + 1] as usize]
+    }
+
+    /// Returns an iterator over the tokens as `(id, bytes)` pairs, in ID order
+    pub fn tokens(&self) -> impl Iterator<Item = (T, &[u8])> + '_ {
+        (0..self.num_tokens()).map(move |id| (T::from_index(id), self.token_bytes(T::from_index(id))))
+    }
+
+    /// Returns the total space (in bytes) used by the token data and token boundaries
+    pub fn space_used(&self) -> usize {
+        self.token_data().len() +
+        self.token_boundaries.len() * std::mem::size_of::<u32>()
+    }
+
+    /// Returns the heap memory (in bytes) allocated by the dictionary, including spare capacity,
+    /// padding and the matcher if it has been built
+    pub fn heap_size(&self) -> usize {
+        self.bytes.capacity() +
+        self.token_boundaries.capacity() * std::mem::size_of::<u32>() +
+        self.lpm.get().map_or(0, |lpm| lpm.heap_size())
+    }
+
+    /// Returns the raw token data, without the trailing padding
+    #[inline]
+    pub(crate) fn token_data(&self) -> &[u8] {
+        &self.bytes[..self.bytes.len() - DICTIONARY_PADDING]
+    }
+
+    /// Shrinks the token storage to fit its current contents
+    pub fn shrink_to_fit(&mut self) {
+        self.bytes.shrink_to_fit();
+        self.token_boundaries.shrink_to_fit();
+    }
+
+    /// Returns the finalized matcher, rebuilding it from the tokens if needed
+    fn matcher(&self) -> &StaticLongestPrefixMatcher<T> {
+        self.lpm.get_or_init(|| {
+            let mut lpm = LongestPrefixMatcher::new();
+            for (id, window) in self.token_boundaries.windows(2).enumerate() {
+                lpm.insert(&self.bytes[window[0] as usize..window[1] as usize], T::from_index(id));
+            }
+            lpm.finalize()
+        })
+    }
+}
+
+impl<T: TokenId> Default for OnPairDictionaryBase<T> {
+    /// Creates an empty, untrained dictionary
+    fn default() -> Self {
+        Self::from_parts(2, Vec::new(), Vec::new())
+    }
+}
+
+impl<T: TokenId> Clone for OnPairDictionaryBase<T> {
+    /// Clones the tokens; the clone rebuilds its matcher on first use
+    fn clone(&self) -> Self {
+        let mut dictionary = Self::from_parts(self.threshold, self.token_data().to_vec(), self.token_boundaries.clone());
+        dictionary.training_report = self.training_report;
+        dictionary
+    }
+}
@@ -0,0 +1,650 @@
+//! Binary serialization format shared by the OnPair compressors
+//!
+//! A serialized collection is a single little-endian blob made of a fixed-size
+//! header followed by four sections:
+//!
+//! ```text
+//! offset  size  field
+//! ------  ----  -----------------------------------------------------------
+//!      0     4  magic bytes "ONPR"
+//!      4     2  format version (currently 1)
+//!      6     1  variant (0 = OnPair, 1 = OnPair16, 2 = OnPair32, 3 = OnPairPacked)
+//!      7     1  reserved, must be 0
+//!      8     2  merging frequency threshold
+//!     10     6  reserved, must be 0
+//!     16     8  dictionary length in bytes
+//!     24     8  number of token boundaries (number of tokens + 1)
+//!     32     8  number of string boundaries (number of strings + 1)
+//!     40     8  number of token IDs in the token stream
+//!     48     -  dictionary bytes           (u8,  zero-padded, see below)
+//!      -     -  token boundaries           (u32, zero-padded to 8 bytes)
+//!      -     -  string boundaries          (u64)
+//!      -     -  token stream               (u16, u32 for OnPair32, see below for OnPairPacked;
+//!                                            zero-padded to 8 bytes)
+//! ```
+//!
+//! Every section starts at an offset that is a multiple of 8, so a blob loaded
+//! at an 8-byte aligned address (e.g. a memory-mapped file) can be reinterpreted
+//! in place. The dictionary section is followed by at least 16 zero bytes, which
+//! keeps the fast-copy decoding loop within the section for the last token.
+//!
+//! The token stream of `OnPairPacked` holds codes of `w` bits packed least
+//! significant bit first, where `w` is the number of bits needed to address every
+//! token of the dictionary; it is derived from the token boundaries rather than
+//! stored.
+
+use crate::compressor::packed::{self, PackedTokens, MAX_WIDTH};
+use crate::compressor::token::TokenId;
+use std::io::{self, Read, Write};
+
+/// Magic bytes identifying a serialized OnPair collection
+pub(crate) const MAGIC: [u8; 4] = *b"ONPR";
+/// Current version of the binary format
+pub(crate) const VERSION: u16 = 1;
+/// Size of the fixed header in bytes
+pub(crate) const HEADER_SIZE: usize = 48;
+/// Minimum number of zero bytes following the dictionary section
+pub(crate) const DICTIONARY_SLACK: usize = 16;
+
+/// Variant tag for `OnPair`
+pub(crate) const VARIANT_ONPAIR: u8 = 0;
+/// Variant tag for `OnPair16`
+pub(crate) const VARIANT_ONPAIR16: u8 = 1;
+/// Variant tag for `OnPair32`
+pub(crate) const VARIANT_ONPAIR32: u8 = 2;
+/// Variant tag for `OnPairPacked`
+pub(crate) const VARIANT_ONPAIR_PACKED: u8 = 3;
+
+/// Decoded header of a serialized collection
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Header {
+    pub threshold: u16,
+    pub dictionary_len: usize,
+    pub n_token_boundaries: usize,
+    pub n_string_boundaries: usize,
+    pub n_tokens: usize,
+}
+
+impl Header {
+    /// Writes the header for the given variant
+    pub fn write<W: Write>(&self, writer: &mut W, variant: u8) -> io::Result<()> {
+        let mut header = [0u8; HEADER_SIZE];
+        header[0..4].copy_from_slice(&MAGIC);
+        header[4..6].copy_from_slice(&VERSION.to_le_bytes());
+        header[6] = variant;
+        header[8..10].copy_from_slice(&self.threshold.to_le_bytes());
+        header[16..24].copy_from_slice(&(self.dictionary_len as u64).to_le_bytes());
+        header[24..32].copy_from_slice(&(self.n_token_boundaries as u64).to_le_bytes());
+        header[32..40].copy_from_slice(&(self.n_string_boundaries as u64).to_le_bytes());
+        header[40..48].copy_from_slice(&(self.n_tokens as u64).to_le_bytes());
+        writer.write_all(&header)
+    }
+
+    /// Parses and validates a header, checking that it describes the expected variant
+    pub fn parse(header: &[u8], variant: u8) -> io::Result<Self> {
+        if header.len() < HEADER_SIZE {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated header"));
+        }
+        if header[0..4] != MAGIC {
+            return Err(invalid_data("bad magic bytes"));
+        }
+        if u16::from_le_bytes([header[4], header[5]]) != VERSION {
+            return Err(invalid_data("unsupported format version"));
+        }
+        if header[6] != variant {
+            return Err(invalid_data("serialized data belongs to a different variant"));
+        }
+        if header[7] != 0 || header[10..16].iter().any(|&byte| byte != 0) {
+            return Err(invalid_data("reserved header bytes must be zero"));
+        }
+
+        let read_u64 = |offset: usize| -> io::Result<usize> {
+            let mut bytes = [0u8; 8];
+            bytes.copy_from_slice(&header[offset..offset + 8]);
+            usize::try_from(u64::from_le_bytes(bytes))
+                .map_err(|_| invalid_data("section length overflows usize"))
+        };
+
+        let header = Self {
+            threshold: u16::from_le_bytes([header[8], header[9]]),
+            dictionary_len: read_u64(16)?,
+            n_token_boundaries: read_u64(24)?,
+            n_string_boundaries: read_u64(32)?,
+            n_tokens: read_u64(40)?,
+        };
+
+        if header.threshold <= 1 {
+            return Err(invalid_data("threshold must be greater than 1"));
+        }
+        Ok(header)
+    }
+
+    /// Returns the byte length of each section, in blob order, for token IDs of `token_bits` bits
+    pub fn section_sizes(&self, token_bits: usize) -> io::Result<[usize; 4]> {
+        let overflow = || invalid_data("section length overflows usize");
+        let dictionary = self.dictionary_len.checked_add(DICTIONARY_SLACK).ok_or_else(overflow)?;
+        let token_boundaries = self.n_token_boundaries.checked_mul(4).ok_or_else(overflow)?;
+        let string_boundaries = self.n_string_boundaries.checked_mul(8).ok_or_else(overflow)?;
+        let tokens = self.n_tokens.checked_mul(token_bits).ok_or_else(overflow)?.div_ceil(8);
+
+        Ok([
+            padded_len(dictionary),
+            padded_len(token_boundaries),
+            string_boundaries,
+            padded_len(tokens),
+        ])
+    }
+}
+
+/// Rounds a section length up to the next multiple of 8
+#[inline]
+pub(crate) fn padded_len(len: usize) -> usize {
+    (len + 7) & !7
+}
+
+/// Writes the four sections of a collection
+pub(crate) fn write_sections<W: Write, T: TokenId>(
+    writer: &mut W,
+    dictionary: &[u8],
+    token_boundaries: &[u32],
+    string_boundaries: impl ExactSizeIterator<Item = usize>,
+    compressed_data: &[T],
+) -> io::Result<()> {
+    write_index_sections(writer, dictionary, token_boundaries, string_boundaries)?;
+
+    let mut bytes = Vec::with_capacity(padded_len(compressed_data.len() * T::SIZE));
+    for &token_id in compressed_data {
+        token_id.write_le(&mut bytes);
+    }
+    bytes.resize(padded_len(bytes.len()), 0);
+    writer.write_all(&bytes)
+}
+
+/// Writes the four sections of a collection whose token stream is already packed
+pub(crate) fn write_packed_sections<W: Write>(
+    writer: &mut W,
+    dictionary: &[u8],
+    token_boundaries: &[u32],
+    string_boundaries: impl ExactSizeIterator<Item = usize>,
+    packed_tokens: &[u8],
+) -> io::Result<()> {
+    write_index_sections(writer, dictionary, token_boundaries, string_boundaries)?;
+
+    writer.write_all(packed_tokens)?;
+    write_padding(writer, padded_len(packed_tokens.len()) - packed_tokens.len())
+}
+
+/// Writes the dictionary, token boundaries and string boundaries sections
+fn write_index_sections<W: Write>(
+    writer: &mut W,
+    dictionary: &[u8],
+    token_boundaries: &[u32],
+    string_boundaries: impl ExactSizeIterator<Item = usize>,
+) -> io::Result<()> {
+    writer.write_all(dictionary)?;
+    write_padding(writer, padded_len(dictionary.len() + DICTIONARY_SLACK) - dictionary.len())?;
+
+    let mut bytes = Vec::with_capacity(padded_len(token_boundaries.len() * 4));
+    for &boundary in token_boundaries {
+        bytes.extend_from_slice(&boundary.to_le_bytes());
+    }
+    bytes.resize(padded_len(bytes.len()), 0);
+    writer.write_all(&bytes)?;
+
+    let mut bytes = Vec::with_capacity(string_boundaries.len() * 8);
+    for boundary in string_boundaries {
+        bytes.extend_from_slice(&(boundary as u64).to_le_bytes());
+    }
+    writer.write_all(&bytes)
+}
+
+/// Owned contents of a deserialized collection
+pub(crate) struct Sections<C = Vec<u16>> {
+    pub threshold: u16,
+    pub dictionary: Vec<u8>,
+    pub token_boundaries: Vec<u32>,
+    pub string_boundaries: Vec<usize>,
+    pub compressed_data: C,
+}
+
+/// Reads and validates a collection of the given variant
+///
+/// `max_token_length` bounds the length of every dictionary entry, which lets
+/// OnPair16 reject blobs its decoder cannot handle.
+pub(crate) fn read_sections<R: Read, T: TokenId>(
+    reader: &mut R,
+    variant: u8,
+    max_token_length: usize,
+) -> io::Result<Sections<Vec<T>>> {
+    let header = read_header(reader, variant)?;
+    let sizes = header.section_sizes(T::SIZE * 8)?;
+    let (dictionary, token_boundaries, string_boundaries) = read_index_sections(reader, &header, &sizes)?;
+
+    let compressed_data = read_bytes(reader, sizes[3])?
+        .chunks_exact(T::SIZE)
+        .take(header.n_tokens)
+        .map(T::read_le)
+        .collect::<Vec<_>>();
+
+    validate(&dictionary, &token_boundaries, &string_boundaries, &compressed_data, max_token_length)?;
+
+    Ok(Sections {
+        threshold: header.threshold,
+        dictionary,
+        token_boundaries,
+        string_boundaries,
+        compressed_data,
+    })
+}
+
+/// Reads and validates a collection whose token stream is packed (see the module documentation)
+pub(crate) fn read_packed_sections<R: Read>(reader: &mut R, variant: u8) -> io::Result<Sections<PackedTokens>> {
+    let header = read_header(reader, variant)?;
+    let width = packed::code_width(header.n_token_boundaries.saturating_sub(1));
+    if width > MAX_WIDTH {
+        return Err(invalid_data("too many tokens for this variant"));
+    }
+
+    let sizes = header.section_sizes(width as usize)?;
+    let (dictionary, token_boundaries, string_boundaries) = read_index_sections(reader, &header, &sizes)?;
+    let compressed_data = PackedTokens::from_bytes(read_bytes(reader, sizes[3])?, width, header.n_tokens);
+
+    let token_ids = compressed_data.iter(0..compressed_data.len()).collect::<Vec<_>>();
+    validate(&dictionary, &token_boundaries, &string_boundaries, &token_ids, usize::MAX)?;
+
+    Ok(Sections {
+        threshold: header.threshold,
+        dictionary,
+        token_boundaries,
+        string_boundaries,
+        compressed_data,
+    })
+}
+
+/// Reads the header, checking that it describes the expected variant
+fn read_header<R: Read>(reader: &mut R, variant: u8) -> io::Result<Header> {
+    let mut header = [0u8; HEADER_SIZE];
+    reader.read_exact(&mut header)?;
+    Header::parse(&header, variant)
+}
+
+/// Reads the dictionary, token boundaries and string boundaries sections
+fn read_index_sections<R: Read>(
+    reader: &mut R,
+    header: &Header,
+    sizes: &[usize; 4],
+) -> io::Result<(Vec<u8>, Vec<u32>, Vec<usize>)> {
+    let mut dictionary = read_bytes(reader, sizes[0])?;
+    dictionary.truncate(header.dictionary_len);
+
+    let token_boundaries = read_bytes(reader, sizes[1])?
+        .chunks_exact(4)
+        .take(header.n_token_boundaries)
+        .map(|chunk| u32::from_le_bytes(chunk.try_into().unwrap()))
+        .collect::<Vec<_>>();
+
+    let string_boundaries = read_bytes(reader, sizes[2])?
+        .chunks_exact(8)
+        .map(|chunk| u64::from_le_bytes(chunk.try_into().unwrap()) as usize)
+        .collect::<Vec<_>>();
+
+    Ok((dictionary, token_boundaries, string_boundaries))
+}
+
+/// Borrowed contents of a serialized collection
+///
+/// The dictionary slice includes the zero padding that follows the dictionary in the
+/// blob, at least 16 bytes, so the fast copy of the last token reads within the slice.
+#[derive(Clone, Copy)]
+pub(crate) struct BorrowedSections<'a, T = u16> {
+    pub threshold: u16,
+    pub dictionary: &'a [u8],
+    pub token_boundaries: &'a [u32],
+    pub string_boundaries: &'a [u64],
+    pub compressed_data: &'a [T],
+}
+
+/// Reinterprets a serialized blob in place, without copying any section
+///
+/// The blob must start at an 8-byte aligned address. When `check_contents` is false
+/// only the header and section sizes are checked, and the caller is responsible for
+/// the blob having been produced by `serialize`.
+pub(crate) fn borrow_sections<T: TokenId>(
+    blob: &[u8],
+    variant: u8,
+    max_token_length: usize,
+    check_contents: bool,
+) -> io::Result<BorrowedSections<'_, T>> {
+    if cfg!(target_endian = "big") {
+        return Err(io::Error::new(io::ErrorKind::Unsupported, "zero-copy views require a little-endian target"));
+    }
+    if blob.as_ptr().align_offset(8) != 0 {
+        return Err(invalid_data("serialized data is not 8-byte aligned"));
+    }
+
+    let header = Header::parse(blob, variant)?;
+    let sizes = header.section_sizes(T::SIZE * 8)?;
+    let mut offsets = [HEADER_SIZE; 5];
+    for i in 0..4 {
+        offsets[i + 1] = offsets[i].checked_add(sizes[i]).ok_or_else(|| invalid_data("section length overflows usize"))?;
+    }
+    if blob.len() < offsets[4] {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated section"));
+    }
+
+    let dictionary = &blob[offsets[0]..offsets[1]];
+    let token_boundaries = &cast_section::<u32>(&blob[offsets[1]..offsets[2]])[..header.n_token_boundaries];
+    let string_boundaries = cast_section::<u64>(&blob[offsets[2]..offsets[3]]);
+    let compressed_data = &cast_section::<T>(&blob[offsets[3]..offsets[4]])[..header.n_tokens];
+
+    if check_contents {
+        validate(&dictionary[..header.dictionary_len], token_boundaries, string_boundaries, compressed_data, max_token_length)?;
+    }
+
+    Ok(BorrowedSections {
+        threshold: header.threshold,
+        dictionary,
+        token_boundaries,
+        string_boundaries,
+        compressed_data,
+    })
+}
+
+/// Reinterprets an 8-byte aligned section as a slice of little-endian integers
+fn cast_section<T: Copy>(section: &[u8]) -> &[T] {
+    // SAFETY: `T` is only instantiated with primitive integers, which are valid for any
+    // bit pattern, and the section start is 8-byte aligned so the prefix is empty.
+    let (prefix, values, _) = unsafe { section.align_to::<T>() };
+    debug_assert!(prefix.is_empty());
+    values
+}
+
+/// Checks the structural invariants the decoders rely on
+///
+/// Decompression indexes the dictionary through raw pointers, so every token ID
+/// must refer to an existing token and every token must lie inside the dictionary.
+pub(crate) fn validate<B, T>(
+    dictionary: &[u8],
+    token_boundaries: &[u32],
+    string_boundaries: &[B],
+    compressed_data: &[T],
+    max_token_length: usize,
+) -> io::Result<()>
+where
+    B: Copy + TryInto<usize>,
+    T: TokenId,
+{
+    // A collection that was never compressed has all four sections empty
+    if token_boundaries.is_empty() {
+        if !dictionary.is_empty() || !string_boundaries.is_empty() || !compressed_data.is_empty() {
+            return Err(invalid_data("missing token boundaries"));
+        }
+        return Ok(());
+    }
+
+    if token_boundaries[0] != 0 || *token_boundaries.last().unwrap() as usize != dictionary.len() {
+        return Err(invalid_data("token boundaries do not span the dictionary"));
+    }
+    for window in token_boundaries.windows(2) {
+        if window[0] > window[1] || (window[1] - window[0]) as usize > max_token_length {
+            return Err(invalid_data("invalid token boundaries"));
+        }
+    }
+
+    let n_tokens = token_boundaries.len() - 1;
+    if compressed_data.iter().any(|&token_id| token_id.index() >= n_tokens) {
+        return Err(invalid_data("token ID out of range"));
+    }
+
+    let mut previous = 0;
+    for (i, &boundary) in string_boundaries.iter().enumerate() {
+        let boundary = boundary.try_into().map_err(|_| invalid_data("string boundary overflows usize"))?;
+        if (i == 0 && boundary != 0) || boundary < previous {
+            return Err(invalid_data("invalid string boundaries"));
+        }
+        previous = boundary;
+    }
+    if string_boundaries.is_empty() && !compressed_data.is_empty() {
+        return Err(invalid_data("missing string boundaries"));
+    }
+    if previous != compressed_data.len() {
+        return Err(invalid_data("string boundaries do not span the token stream"));
+    }
+
+    Ok(())
+}
+
+/// Creates an `InvalidData` I/O error with the given message
+pub(crate) fn invalid_data(message: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn write_padding<W: Write>(writer: &mut W, len: usize) -> io::Result<()> {
+    writer.write_all(&[0u8; 16][..len % 16])?;
+    for _ in 0..len / 16 {
+        writer.write_all(&[0u8; 16])?;
+    }
+    Ok(())
+}
+
+fn read_bytes<R: Read>(reader: &mut R, len: usize) -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    reader.take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != len {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated section"));
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compressor::testing::{self, assert_strings, compressed, serialized};
+    use crate::compressor::{OnPair, OnPair16, OnPair16View, OnPair32, OnPair32View, OnPairConfig, OnPairView};
+
+    /// Checks that both variants deserialize and view `strings` and serialize them back identically
+    fn check_roundtrip(strings: &[String], config: OnPairConfig) {
+        let bytes = serialized(&compressed::<OnPair>(strings, config));
+        assert_eq!(bytes.len() % 8, 0);
+        let compressor = OnPair::deserialize(&bytes[..]).unwrap();
+        assert_strings(&compressor, strings);
+        assert_eq!(serialized(&compressor), bytes);
+        let words = testing::aligned(&bytes);
+        assert_strings(&OnPairView::new(testing::blob(&words, bytes.len())).unwrap(), strings);
+
+        let bytes = serialized(&compressed::<OnPair16>(strings, config));
+        assert_eq!(bytes.len() % 8, 0);
+        let compressor = OnPair16::deserialize(&bytes[..]).unwrap();
+        assert_strings(&compressor, strings);
+        assert_eq!(serialized(&compressor), bytes);
+        let words = testing::aligned(&bytes);
+        assert_strings(&OnPair16View::new(testing::blob(&words, bytes.len())).unwrap(), strings);
+    }
+
+    #[test]
+    fn roundtrip() {
+        let mut strings = testing::urls(200, 4);
+        strings[10].clear();
+        check_roundtrip(&strings, OnPairConfig::new(3).with_seed(4));
+    }
+
+    #[test]
+    fn roundtrip_empty_collection() {
+        check_roundtrip(&[], OnPairConfig::new(3));
+        check_roundtrip(&[String::new(), String::new()], OnPairConfig::new(3));
+    }
+
+    #[test]
+    fn roundtrip_untrained() {
+        let mut bytes = Vec::new();
+        OnPair::new(3).serialize(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), HEADER_SIZE + padded_len(DICTIONARY_SLACK));
+        assert!(OnPair::deserialize(&bytes[..]).unwrap().is_empty());
+        let words = testing::aligned(&bytes);
+        assert!(OnPairView::new(testing::blob(&words, bytes.len())).unwrap().is_empty());
+
+        let mut bytes = Vec::new();
+        OnPair16::new(3).serialize(&mut bytes).unwrap();
+        assert!(OnPair16::deserialize(&bytes[..]).unwrap().is_empty());
+        let words = testing::aligned(&bytes);
+        assert!(OnPair16View::new(testing::blob(&words, bytes.len())).unwrap().is_empty());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn roundtrip_wide_dictionary() {
+        // A low threshold on repetitive strings fills the dictionary with long tokens
+        let strings = testing::urls(5000, 5);
+        let config = OnPairConfig::new(2).with_seed(5);
+        check_roundtrip(&strings, config);
+
+        let compressor: OnPair32 = compressed(&strings, config);
+        assert!(compressor.tokens().any(|(_, bytes, _)| bytes.len() > 16));
+
+        let bytes = serialized(&compressor);
+        let offsets = section_offsets(&bytes, VARIANT_ONPAIR32, 32);
+        assert_eq!(offsets[4] - offsets[3], padded_len(compressor.stats().n_parsed_tokens * 4));
+
+        let deserialized = OnPair32::deserialize(&bytes[..]).unwrap();
+        assert_strings(&deserialized, &strings);
+        let words = testing::aligned(&bytes);
+        assert_strings(&OnPair32View::new(testing::blob(&words, bytes.len())).unwrap(), &strings);
+    }
+
+    fn error_kind<T>(result: io::Result<T>) -> Option<io::ErrorKind> {
+        result.err().map(|error| error.kind())
+    }
+
+    /// Checks that both the owned deserialization and the view reject an `OnPair` blob
+    fn assert_rejected(bytes: &[u8], kind: io::ErrorKind) {
+        assert_eq!(error_kind(OnPair::deserialize(bytes)), Some(kind));
+        let words = testing::aligned(bytes);
+        assert_eq!(error_kind(OnPairView::new(testing::blob(&words, bytes.len()))), Some(kind));
+    }
+
+    /// Returns the offsets of the four sections of a blob, followed by its end
+    fn section_offsets(bytes: &[u8], variant: u8, token_bits: usize) -> [usize; 5] {
+        let sizes = Header::parse(bytes, variant).unwrap().section_sizes(token_bits).unwrap();
+        let mut offsets = [HEADER_SIZE; 5];
+        for i in 0..4 {
+            offsets[i + 1] = offsets[i] + sizes[i];
+        }
+        offsets
+    }
+
+    fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    fn write_u32(bytes: &mut [u8], offset: usize, value: u32) {
+        bytes[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn write_u64(bytes: &mut [u8], offset: usize, value: u64) {
+        bytes[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+    }
+
+    #[test]
+    fn reject_bad_header() {
+        let bytes = serialized(&compressed::<OnPair>(&testing::urls(50, 6), OnPairConfig::new(3).with_seed(6)));
+        OnPair::deserialize(&bytes[..]).unwrap();
+
+        let corrupt = |offset: usize, value: u8| {
+            let mut bytes = bytes.clone();
+            bytes[offset] = value;
+            bytes
+        };
+        assert_rejected(&corrupt(0, b'X'), io::ErrorKind::InvalidData);
+        assert_rejected(&corrupt(4, VERSION as u8 + 1), io::ErrorKind::InvalidData);
+        assert_rejected(&corrupt(5, 1), io::ErrorKind::InvalidData);
+        assert_rejected(&corrupt(8, 1), io::ErrorKind::InvalidData);
+        assert_rejected(&corrupt(7, 1), io::ErrorKind::InvalidData);
+        for offset in 10..16 {
+            assert_rejected(&corrupt(offset, 1), io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn reject_wrong_variant() {
+        let bytes = serialized(&compressed::<OnPair>(&testing::urls(50, 7), OnPairConfig::new(3).with_seed(7)));
+
+        assert_eq!(error_kind(OnPair16::deserialize(&bytes[..])), Some(io::ErrorKind::InvalidData));
+        assert_eq!(error_kind(OnPair32::deserialize(&bytes[..])), Some(io::ErrorKind::InvalidData));
+        let words = testing::aligned(&bytes);
+        assert_eq!(error_kind(OnPair16View::new(testing::blob(&words, bytes.len()))), Some(io::ErrorKind::InvalidData));
+    }
+
+    #[test]
+    fn reject_truncated() {
+        let bytes = serialized(&compressed::<OnPair>(&testing::urls(50, 8), OnPairConfig::new(3).with_seed(8)));
+        let offsets = section_offsets(&bytes, VARIANT_ONPAIR, 16);
+        assert_eq!(offsets[4], bytes.len());
+
+        let mut cuts = vec![0, HEADER_SIZE / 2, HEADER_SIZE, bytes.len() - 1];
+        for &offset in &offsets[1..4] {
+            cuts.extend([offset - 1, offset, offset + 1]);
+        }
+        for cut in cuts {
+            assert_rejected(&bytes[..cut], io::ErrorKind::UnexpectedEof);
+        }
+    }
+
+    #[test]
+    fn reject_corrupted_boundaries() {
+        let bytes = serialized(&compressed::<OnPair>(&testing::urls(50, 9), OnPairConfig::new(3).with_seed(9)));
+        let header = Header::parse(&bytes, VARIANT_ONPAIR).unwrap();
+        let offsets = section_offsets(&bytes, VARIANT_ONPAIR, 16);
+        let token_boundary = |index: usize| offsets[1] + 4 * index;
+        let string_boundary = |index: usize| offsets[2] + 8 * index;
+
+        // Token boundaries that do not end at the dictionary length
+        let mut corrupted = bytes.clone();
+        let last = header.n_token_boundaries - 1;
+        write_u32(&mut corrupted, token_boundary(last), header.dictionary_len as u32 - 1);
+        assert_rejected(&corrupted, io::ErrorKind::InvalidData);
+
+        // Token boundaries that do not start at 0
+        let mut corrupted = bytes.clone();
+        write_u32(&mut corrupted, token_boundary(0), 1);
+        assert_rejected(&corrupted, io::ErrorKind::InvalidData);
+
+        // Decreasing token boundaries
+        let mut corrupted = bytes.clone();
+        write_u32(&mut corrupted, token_boundary(1), read_u32(&bytes, token_boundary(2)) + 1);
+        assert_rejected(&corrupted, io::ErrorKind::InvalidData);
+
+        // Token ID without a token
+        let mut corrupted = bytes.clone();
+        corrupted[offsets[3]..offsets[3] + 2].copy_from_slice(&(header.n_token_boundaries as u16 - 1).to_le_bytes());
+        assert_rejected(&corrupted, io::ErrorKind::InvalidData);
+
+        // String boundaries that do not start at 0
+        let mut corrupted = bytes.clone();
+        write_u64(&mut corrupted, string_boundary(0), 1);
+        assert_rejected(&corrupted, io::ErrorKind::InvalidData);
+
+        // Decreasing string boundaries
+        let mut corrupted = bytes.clone();
+        write_u64(&mut corrupted, string_boundary(2), 0);
+        assert_rejected(&corrupted, io::ErrorKind::InvalidData);
+
+        // String boundaries that do not end at the number of token IDs
+        let mut corrupted = bytes.clone();
+        let last = header.n_string_boundaries - 1;
+        write_u64(&mut corrupted, string_boundary(last), header.n_tokens as u64 - 1);
+        assert_rejected(&corrupted, io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn reject_tokens_too_long_for_onpair16() {
+        // Relabeling an OnPair blob whose dictionary has tokens longer than 16 bytes
+        let mut bytes = serialized(&compressed::<OnPair>(&testing::urls(500, 10), OnPairConfig::new(2).with_seed(10)));
+        let compressor = OnPair::deserialize(&bytes[..]).unwrap();
+        assert!(compressor.tokens().any(|(_, bytes, _)| bytes.len() > 16));
+
+        bytes[6] = VARIANT_ONPAIR16;
+        assert_eq!(error_kind(OnPair16::deserialize(&bytes[..])), Some(io::ErrorKind::InvalidData));
+        let words = testing::aligned(&bytes);
+        assert_eq!(error_kind(OnPair16View::new(testing::blob(&words, bytes.len()))), Some(io::ErrorKind::InvalidData));
+    }
+}
@@ -1,5 +1,57 @@
+pub mod bitmap;
+mod boundaries;
+pub mod config;
+pub mod dictionary;
+pub mod dictionary16;
+pub mod format;
 pub mod onpair;
 pub mod onpair16;
+pub mod onpair_packed;
+mod packed;
+#[cfg(feature = "parallel")]
+mod parallel;
+#[cfg(feature = "regex")]
+mod regex;
+pub mod sample;
+mod search;
+pub mod shuffle;
+pub mod size;
+pub mod stats;
+mod storage;
+#[cfg(test)]
+pub(crate) mod testing;
+mod token;
+pub mod traits;
+pub mod view;
 
-pub use onpair::OnPair;
-pub use onpair16::OnPair16;
\ No newline at end of file
+pub use bitmap::Bitmap;
+pub use config::OnPairConfig;
+pub use dictionary::{OnPair32Dictionary, OnPairDictionary, OnPairDictionaryBase};
+pub use dictionary16::OnPair16Dictionary;
+pub use onpair::{OnPair, OnPair32, OnPairBase};
+pub use onpair16::OnPair16;
+pub use onpair_packed::OnPairPacked;
+pub use packed::PackedTokens;
+pub use sample::{Sample, SampleStrategy, TrainingReport};
+pub use shuffle::Shuffle;
+pub use size::SizeReport;
+pub use stats::CompressionStats;
+pub use storage::TokenStorage;
+pub use token::TokenId;
+pub use traits::{CompressedStrings, StringCompressor};
+pub use view::{OnPair16View, OnPair32View, OnPairView};
+
+// All read paths take `&self`, so compressed collections can be shared across threads (e.g. in an `Arc`)
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<OnPair>();
+    assert_send_sync::<OnPair16>();
+    assert_send_sync::<OnPair32>();
+    assert_send_sync::<OnPairPacked>();
+    assert_send_sync::<OnPairDictionary>();
+    assert_send_sync::<OnPair16Dictionary>();
+    assert_send_sync::<OnPair32Dictionary>();
+    assert_send_sync::<OnPairView<'static>>();
+    assert_send_sync::<OnPair16View<'static>>();
+    assert_send_sync::<OnPair32View<'static>>();
+};
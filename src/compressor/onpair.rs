@@ -1,54 +1,147 @@
-use crate::lpm::LongestPrefixMatcher;
-use rustc_hash::FxHashMap;
-use rand::seq::SliceRandom;
-use rand::thread_rng;
+use crate::compressor::bitmap::Bitmap;
+use crate::compressor::dictionary::OnPairDictionaryBase;
+use crate::compressor::boundaries::StringBoundaries;
+use crate::compressor::format::{Header, Sections};
+#[cfg(feature = "regex")]
+use crate::compressor::regex::RegexAutomaton;
+use crate::compressor::sample::TrainingReport;
+use crate::compressor::search::{self, SubstringAutomaton};
+use crate::compressor::config::OnPairConfig;
+use crate::compressor::shuffle::Shuffle;
+use crate::compressor::size::SizeReport;
+use crate::compressor::stats::{self, CompressionStats};
+use crate::compressor::storage::TokenStorage;
+use crate::compressor::token::TokenId;
+use crate::error::{self, OnPairError};
+use std::io::{self, Read, Write};
 
 const FAST_COPY_SIZE: usize = 16;
 
-pub struct OnPair {
-    // Merging frequency threshold
-    threshold: u16,
+/// OnPair collection with 16-bit token IDs (up to 65,536 tokens of arbitrary length)
+pub type OnPair = OnPairBase<u16>;
+
+/// OnPair collection with 32-bit token IDs (up to 16,777,216 tokens of arbitrary length)
+///
+/// Suited to high-cardinality data, on which a 65,536-token dictionary fills up early.
+pub type OnPair32 = OnPairBase<u32>;
+
+/// OnPair collection storing token IDs of type `T` in the layout `S`
+///
+/// `S` is either `Vec<T>`, one token ID per element, or `PackedTokens`, bit-packed
+/// codes (see [`OnPairPacked`](crate::compressor::OnPairPacked)). Searching the
+/// compressed strings requires the `Vec<T>` layout.
+pub struct OnPairBase<T: TokenId, S: TokenStorage<T> = Vec<T>> {
+    // Training parameters
+    config: OnPairConfig,                // Threshold, limits, shuffling and sampling
 
     // Compressed data storage
-    compressed_data: Vec<u16>,           // Sequence of token IDs
-    string_boundaries: Vec<usize>,       // End positions for each string
+    pub(crate) compressed_data: S,       // Sequence of token IDs
+    string_boundaries: StringBoundaries, // End positions for each string
     
     // Dictionary storage  
-    dictionary: Vec<u8>,                 // Raw token data
-    token_boundaries: Vec<u32>,          // Token end positions in dictionary
+    dictionary: OnPairDictionaryBase<T>,
 }
 
-impl OnPair {
+impl<T: TokenId, S: TokenStorage<T>> OnPairBase<T, S> {
     /// Creates a new compressor instance
     pub fn new(threshold: u16) -> Self {
         assert!(threshold > 1, "Threshold must be greater than 1");
-        
-        Self {
-            threshold,
-            compressed_data: Vec::new(),
-            string_boundaries: Vec::new(),
-            dictionary: Vec::new(),
-            token_boundaries: Vec::new(),
-        }
+        Self::with_config(OnPairConfig::new(threshold))
     }
     
     /// Creates a new compressor with capacity hints for better memory allocation
     pub fn with_capacity(threshold: u16, n_strings: usize, n_bytes: usize) -> Self {
-        assert!(threshold > 1, "Threshold must be greater than 1");
+        let mut compressor = Self::new(threshold);
+        // The width of packed codes is only known after training, so reserve for the widest codes
+        compressor.compressed_data = S::for_tokens(T::MAX_TOKENS);
+        compressor.compressed_data.reserve_tokens(n_bytes);
+        compressor.string_boundaries.reserve(n_strings);
+        compressor
+    }
 
+    /// Creates a new compressor instance, rejecting thresholds not greater than 1
+    pub fn try_new(threshold: u16) -> Result<Self, OnPairError> {
+        error::check_threshold(threshold)?;
+        Ok(Self::new(threshold))
+    }
+
+    /// Creates a new compressor instance that trains with the given configuration
+    ///
+    /// # Panics
+    /// Panics if the configuration is invalid (see [`try_with_config`](Self::try_with_config)).
+    pub fn with_config(config: OnPairConfig) -> Self {
+        match Self::try_with_config(config) {
+            Ok(compressor) => compressor,
+            Err(error) => panic!("Invalid configuration: {}", error),
+        }
+    }
+
+    /// Creates a new compressor instance, rejecting invalid configurations
+    ///
+    /// Returns an error if the threshold is not greater than 1, the maximum token count is
+    /// not between 256 and 65,536 (16,777,216 for `OnPair32`), the maximum token length is
+    /// out of range for this variant, or the sample fraction is not in `(0, 1]`.
+    pub fn try_with_config(config: OnPairConfig) -> Result<Self, OnPairError> {
+        config.validate(usize::MAX, T::MAX_TOKENS)?;
+
+        Ok(Self {
+            config,
+            compressed_data: S::for_tokens(0),
+            string_boundaries: StringBoundaries::new(),
+            dictionary: OnPairDictionaryBase::default(),
+        })
+    }
+
+    /// Creates an empty collection that encodes with a pre-trained dictionary
+    ///
+    /// The dictionary can be trained once on a sample with [`OnPairDictionaryBase::train`] and
+    /// shared (by cloning) across many independent collections or partitions.
+    ///
+    /// The dictionary only records its merging threshold, so [`config`](Self::config) returns
+    /// `OnPairConfig::new(threshold)`: the other settings the dictionary was trained with are
+    /// not kept, and retraining with `compress_strings` or `compress_bytes` uses their defaults.
+    pub fn with_dictionary(dictionary: OnPairDictionaryBase<T>) -> Self {
         Self {
-            threshold,
-            compressed_data: Vec::with_capacity(n_bytes),
-            string_boundaries: Vec::with_capacity(n_strings),
-            dictionary: Vec::with_capacity(1024 * 1024),
-            token_boundaries: Vec::with_capacity(1 << 16),
+            config: OnPairConfig::new(dictionary.threshold()),
+            compressed_data: S::for_tokens(dictionary.num_tokens()),
+            string_boundaries: StringBoundaries::new(),
+            dictionary,
         }
     }
 
+    /// Sets the order in which training visits the strings
+    ///
+    /// Use `Shuffle::Seeded` or `Shuffle::Disabled` to make compression reproducible.
+    pub fn set_shuffle(&mut self, shuffle: Shuffle) {
+        self.config.shuffle = shuffle;
+    }
+
+    /// Returns the training configuration
+    #[inline]
+    pub fn config(&self) -> &OnPairConfig {
+        &self.config
+    }
+
+    /// Returns the dictionary used to encode this collection
+    #[inline]
+    pub fn dictionary(&self) -> &OnPairDictionaryBase<T> {
+        &self.dictionary
+    }
+
+    /// Returns which strings the last training visited
+    ///
+    /// Training stops as soon as the dictionary is full, so `trained_strings` can be much
+    /// smaller than `sampled_strings` on large inputs. Returns `None` if the dictionary was
+    /// provided or deserialized rather than trained.
+    #[inline]
+    pub fn training_report(&self) -> Option<TrainingReport> {
+        self.dictionary.training_report()
+    }
+
     /// Compresses a collection of strings
     /// 
     /// This is a convenience method that handles the flattening for you.
-    pub fn compress_strings<S: AsRef<str>>(&mut self, strings: &[S]) {
+    pub fn compress_strings<A: AsRef<str>>(&mut self, strings: &[A]) {
         let (data, end_positions) = flatten_strings(strings);
         self.compress_bytes(&data, &end_positions);
     }
@@ -58,216 +151,533 @@ impl OnPair {
     /// The `end_positions` should be a prefix sum array starting with 0.
     /// For example, if you have strings of lengths [3, 2, 4], 
     /// then `end_positions` should be [0, 3, 5, 9].
+    ///
+    /// A new dictionary is trained on the data (or on the sample selected by the
+    /// configuration), replacing any previous contents. Every string is compressed.
     pub fn compress_bytes(&mut self, data: &[u8], end_positions: &[usize]) {
-        let lpm = self.train_dictionary(data, end_positions);
-        self.parse_data(data, end_positions, &lpm);
+        // Phase 1: Dictionary population
+        self.dictionary = OnPairDictionaryBase::train_with_config(data, end_positions, &self.config);
+
+        // Phase 2: String compression using learned dictionary
+        self.compressed_data.reset(self.dictionary.num_tokens());
+        self.string_boundaries.clear();
+        self.parse(data, end_positions);
     }
 
-    /// Phase 1: Dictionary population
-    /// 
-    /// Uses longest prefix matching to parse training data and identify frequent
-    /// adjacent token pairs.
-    /// 
-    /// # Algorithm
-    /// 1. Initialize 256 single-byte tokens  
-    /// 2. Parse shuffled training data with longest prefix matching
-    /// 3. Track adjacent token pair frequencies
-    /// 4. Merge frequent pairs into new tokens until dictionary full (65,536 tokens)
-    fn train_dictionary(&mut self, data: &[u8], end_positions: &[usize]) -> LongestPrefixMatcher<u16> {
-        self.token_boundaries.push(0);
-        
-        let mut frequency: FxHashMap<(u16, u16), u16> = FxHashMap::default();
-        let mut lpm = LongestPrefixMatcher::new();
-        let mut next_token_id = 256;
-    
-        // Initialize the dictionary with single-byte tokens
-        for i in 0..256 {
-            let token = vec![i as u8];
-            lpm.insert(&token, i as u16);
-            self.dictionary.extend(&token);
-            self.token_boundaries.push(self.dictionary.len() as u32);
+    /// Compresses pre-flattened byte data with end positions, validating the input first
+    ///
+    /// Returns an error if `end_positions` does not start at 0, is not non-decreasing or
+    /// does not end at `data.len()`. An empty collection (`end_positions` equal to `[]` or
+    /// `[0]` with empty data) is accepted.
+    pub fn try_compress_bytes(&mut self, data: &[u8], end_positions: &[usize]) -> Result<(), OnPairError> {
+        error::check_end_positions(data, end_positions)?;
+        self.compress_bytes(data, end_positions);
+        Ok(())
+    }
+
+    /// Appends a string, encoding it with the current dictionary
+    ///
+    /// The dictionary is frozen: no new tokens are learned from appended strings.
+    ///
+    /// # Panics
+    /// Panics if no dictionary has been trained or provided.
+    #[inline]
+    pub fn push_str(&mut self, string: &str) {
+        self.push_bytes(string.as_bytes());
+    }
+
+    /// Appends a byte string, encoding it with the current dictionary
+    ///
+    /// # Panics
+    /// Panics if no dictionary has been trained or provided.
+    #[inline]
+    pub fn push_bytes(&mut self, bytes: &[u8]) {
+        self.extend_bytes(bytes, &[0, bytes.len()]);
+    }
+
+    /// Appends pre-flattened byte data with end positions, encoding it with the current dictionary
+    ///
+    /// The `end_positions` follow the same convention as in [`compress_bytes`](Self::compress_bytes).
+    ///
+    /// # Panics
+    /// Panics if no dictionary has been trained or provided.
+    pub fn extend_bytes(&mut self, data: &[u8], end_positions: &[usize]) {
+        assert!(self.dictionary.num_tokens() > 0, "Cannot append strings without a trained dictionary");
+        self.parse(data, end_positions);
+    }
+
+    /// Appends pre-flattened byte data with end positions, validating the input first
+    ///
+    /// Returns an error if no dictionary has been trained or provided, or if `end_positions`
+    /// is invalid (see [`try_compress_bytes`](Self::try_compress_bytes)).
+    pub fn try_extend_bytes(&mut self, data: &[u8], end_positions: &[usize]) -> Result<(), OnPairError> {
+        if self.dictionary.num_tokens() == 0 {
+            return Err(OnPairError::MissingDictionary);
         }
+        error::check_end_positions(data, end_positions)?;
+        self.parse(data, end_positions);
+        Ok(())
+    }
 
-        // Shuffle entries
-        let mut shuffled_indices: Vec<usize> = (0..end_positions.len()-1).collect();
-        shuffled_indices.shuffle(&mut thread_rng());
-        
-        // Iterate over entries
-        'outer: for &index in shuffled_indices.iter() {
-            let start = end_positions[index];
-            let end = end_positions[index + 1];
-
-            if start == end {
-                continue;
-            }
-    
-            let (match_token_id, match_length) = lpm.find_longest_match(&data[start..end]).unwrap();
-            let mut previous_token_id = match_token_id;
-            let mut previous_length = match_length;
+    /// Parses strings with the dictionary, on multiple threads with the `parallel` feature
+    ///
+    /// The end positions are parsed into a temporary `usize` buffer and then stored compactly.
+    fn parse(&mut self, data: &[u8], end_positions: &[usize]) {
+        let dictionary = &self.dictionary;
+        let mut string_boundaries = Vec::with_capacity(end_positions.len());
+        self.compressed_data.append_with(&mut string_boundaries, |token_ids, boundaries| {
+            #[cfg(feature = "parallel")]
+            dictionary.par_encode(data, end_positions, token_ids, boundaries);
+            #[cfg(not(feature = "parallel"))]
+            dictionary.encode(data, end_positions, token_ids, boundaries);
+        });
 
-            let mut pos = start + previous_length;
-    
-            while pos < end {
-                // Find the longest match
-                let (match_token_id, match_length) = lpm.find_longest_match(&data[pos..end]).unwrap();
-    
-                 // Update token frequency and possibly merge tokens
-                *frequency.entry((previous_token_id, match_token_id)).or_insert(0) += 1;
-    
-                if frequency[&(previous_token_id, match_token_id)] >= self.threshold {
-                    let merged_token = &data[pos - previous_length..pos + match_length];
-                    lpm.insert(merged_token, next_token_id);
-                    self.dictionary.extend(merged_token);
-                    self.token_boundaries.push(self.dictionary.len() as u32);
-                    
-                    frequency.remove(&(previous_token_id, match_token_id));
-                    previous_token_id = next_token_id;
-                    previous_length = merged_token.len();
-
-                    if next_token_id == u16::MAX {
-                        break 'outer;
-                    }
-
-                    next_token_id += 1;
-                }
-                else {
-                    previous_token_id = match_token_id;
-                    previous_length = match_length;
-                }
-            
-                pos += match_length;
-            }
+        // The positions are absolute, starting with the previous end of the token stream
+        if self.string_boundaries.is_empty() {
+            self.string_boundaries.push(string_boundaries[0]);
         }
-    
-        lpm
+        self.string_boundaries.extend_shifted(&string_boundaries[1..], 0);
     }
 
-    /// Phase 2: String compression using learned dictionary
-    /// 
-    /// Compresses each string independently by greedily applying longest prefix matching
-    /// with the constructed dictionary. Each string becomes a sequence of token IDs.
-    fn parse_data(&mut self, data: &[u8], end_positions: &[usize], lpm: &LongestPrefixMatcher<u16>) {
-        self.string_boundaries.push(0);
-
-        for window in end_positions.windows(2) {
-            let start = window[0];
-            let end = window[1];
-
-            if start == end {
-                self.string_boundaries.push(self.compressed_data.len());
-                continue;
-            }
-    
-            let mut pos = start;
-            while pos < end {
-                // Find the longest match
-                let (token_id, length) = lpm.find_longest_match(&data[pos..end]).unwrap();
-                self.compressed_data.push(token_id);    
-                pos += length;
-            }
-    
-            self.string_boundaries.push(self.compressed_data.len());
+    /// Returns the number of strings in the collection
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.string_boundaries.len().saturating_sub(1)
+    }
+
+    /// Returns `true` if the collection contains no strings
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the length in bytes of a specific string once decompressed
+    #[inline]
+    pub fn decompressed_len(&self, index: usize) -> usize {
+        let tokens = self.string_boundaries.range(index);
+        decoded_len(self.compressed_data.token_ids(tokens), &self.dictionary.token_boundaries)
+    }
+
+    /// Decompresses a specific string by index, appending it to `buffer`
+    ///
+    /// Returns the number of bytes appended. The buffer grows as needed, so this method is
+    /// safe for any input while still using the 16-byte fast copy internally.
+    #[inline]
+    pub fn decompress_into(&self, index: usize, buffer: &mut Vec<u8>) -> usize {
+        let tokens = self.string_boundaries.range(index);
+
+        let length = decoded_len(self.compressed_data.token_ids(tokens.clone()), &self.dictionary.token_boundaries);
+        buffer.reserve(length + FAST_COPY_SIZE);
+
+        unsafe {
+            let dst = buffer.as_mut_ptr().add(buffer.len());
+            let token_ids = self.compressed_data.token_ids(tokens);
+            let size = decode_token_ids(token_ids, self.dictionary.bytes.as_ptr(), &self.dictionary.token_boundaries, dst);
+            buffer.set_len(buffer.len() + size);
+            size
         }
     }
 
+    /// Returns a specific string by index as a newly allocated vector
+    #[inline]
+    pub fn get(&self, index: usize) -> Vec<u8> {
+        let mut string = Vec::new();
+        self.decompress_into(index, &mut string);
+        string
+    }
+
     /// Decompresses a specific string by index
     /// 
-    /// # Safety Warning
+    /// # Safety
     /// This method uses unsafe memory operations for performance. For each token, it initially
     /// copies 16 bytes regardless of the actual token length (for optimization), then copies
     /// any remaining bytes if the token is longer than 16 bytes.
     /// 
     /// The buffer must have sufficient space beyond the actual decompressed data to accommodate
-    /// the initial 16-byte copy for the last token, or undefined behavior will occur.
+    /// the initial 16-byte copy for the last token, i.e. at least `decompressed_len(index) + 16`
+    /// bytes, or undefined behavior will occur. See [`decompress_into`](Self::decompress_into) for a safe alternative.
     #[inline]
-    pub fn decompress_string(&mut self, index: usize, buffer: &mut [u8]) -> usize {
-        let string_start = self.string_boundaries[index];
-        let string_end = self.string_boundaries[index + 1];
-        let dict_ptr = self.dictionary.as_ptr();
-        let end_positions_ptr = self.token_boundaries.as_ptr();
-        let mut size = 0;
-
-        for &token_id in self.compressed_data[string_start..string_end].iter() {
-            unsafe {
-                let dict_start = *end_positions_ptr.add(token_id as usize) as usize;
-                let dict_end = *end_positions_ptr.add(token_id as usize + 1) as usize;
-                let length = dict_end - dict_start;
-
-                let mut src = dict_ptr.add(dict_start);
-                let mut dst = buffer.as_mut_ptr().add(size);
-                std::ptr::copy_nonoverlapping(src, dst, FAST_COPY_SIZE);
-
-                if length > FAST_COPY_SIZE {
-                    src = src.add(FAST_COPY_SIZE); 
-                    dst = dst.add(FAST_COPY_SIZE);
-                    std::ptr::copy_nonoverlapping(src, dst, length - FAST_COPY_SIZE);
-                }
-
-                size += length;
-            }
+    pub unsafe fn decompress_string(&self, index: usize, buffer: &mut [u8]) -> usize {
+        let token_ids = self.compressed_data.token_ids(self.string_boundaries.range(index));
+        decode_token_ids(token_ids, self.dictionary.bytes.as_ptr(), &self.dictionary.token_boundaries, buffer.as_mut_ptr())
+    }
+
+    /// Decompresses a specific string by index into a caller-provided buffer, checking all bounds
+    ///
+    /// Returns an error if the index is out of range or if the buffer is shorter than
+    /// `decompressed_len(index) + 16` bytes, the room needed by the 16-byte fast copy.
+    pub fn try_decompress_string(&self, index: usize, buffer: &mut [u8]) -> Result<usize, OnPairError> {
+        if index >= self.len() {
+            return Err(OnPairError::IndexOutOfBounds { index, len: self.len() });
         }
 
-        size
+        let required = self.decompressed_len(index) + FAST_COPY_SIZE;
+        if buffer.len() < required {
+            return Err(OnPairError::BufferTooSmall { required, available: buffer.len() });
+        }
+
+        Ok(unsafe { self.decompress_string(index, buffer) })
+    }
+
+    /// Decompresses all strings, appending them to `buffer`
+    ///
+    /// Returns the number of bytes appended.
+    pub fn decompress_all_into(&self, buffer: &mut Vec<u8>) -> usize {
+        let tokens = 0..self.compressed_data.n_tokens();
+
+        let length = decoded_len(self.compressed_data.token_ids(tokens.clone()), &self.dictionary.token_boundaries);
+        buffer.reserve(length + FAST_COPY_SIZE);
+
+        unsafe {
+            let dst = buffer.as_mut_ptr().add(buffer.len());
+            let token_ids = self.compressed_data.token_ids(tokens);
+            let size = decode_token_ids(token_ids, self.dictionary.bytes.as_ptr(), &self.dictionary.token_boundaries, dst);
+            buffer.set_len(buffer.len() + size);
+            size
+        }
     }
 
     /// Decompresses all strings
     /// 
-    /// # Safety Warning
+    /// # Safety
     /// This method uses unsafe memory operations for performance. For each token, it initially
     /// copies 16 bytes regardless of the actual token length (for optimization), then copies
     /// any remaining bytes if the token is longer than 16 bytes.
     /// 
     /// The buffer must have sufficient space beyond the actual decompressed data to accommodate
     /// the initial 16-byte copy for the last token, or undefined behavior will occur.
-    pub fn decompress_all(&self, buffer: &mut [u8]) -> usize {
-        let dict_ptr = self.dictionary.as_ptr();
-        let end_positions_ptr = self.token_boundaries.as_ptr();
-        let mut size = 0;
-
-        for &token_id in self.compressed_data.iter(){
-            unsafe {
-                let dict_start = *end_positions_ptr.add(token_id as usize) as usize;
-                let dict_end = *end_positions_ptr.add(token_id as usize + 1) as usize;
-                let length = dict_end - dict_start;
-
-                let mut src = dict_ptr.add(dict_start);
-                let mut dst = buffer.as_mut_ptr().add(size);
-                std::ptr::copy_nonoverlapping(src, dst, FAST_COPY_SIZE);
-
-                if length > FAST_COPY_SIZE {
-                    src = src.add(FAST_COPY_SIZE);
-                    dst = dst.add(FAST_COPY_SIZE);
-                    std::ptr::copy_nonoverlapping(src, dst, length - FAST_COPY_SIZE);
-                }
-
-                size += length;
-            }
+    /// See [`decompress_all_into`](Self::decompress_all_into) for a safe alternative.
+    pub unsafe fn decompress_all(&self, buffer: &mut [u8]) -> usize {
+        let token_ids = self.compressed_data.token_ids(0..self.compressed_data.n_tokens());
+        decode_token_ids(token_ids, self.dictionary.bytes.as_ptr(), &self.dictionary.token_boundaries, buffer.as_mut_ptr())
+    }
+
+    /// Returns the total space (in bytes) used by the compressed data, string boundaries and dictionary
+    pub fn space_used(&self) -> usize {
+        self.compressed_data.payload_size() +
+        self.string_boundaries.space_used() +
+        self.dictionary.space_used()
+    }
+
+    /// Returns the heap memory (in bytes) allocated by the collection, including spare capacity,
+    /// padding and the dictionary matcher if it has been built
+    pub fn heap_size(&self) -> usize {
+        self.compressed_data.heap_size() +
+        self.string_boundaries.heap_size() +
+        self.dictionary.heap_size()
+    }
+
+    /// Returns a breakdown of the memory used by the collection
+    pub fn size_report(&self) -> SizeReport {
+        SizeReport {
+            payload: self.compressed_data.payload_size(),
+            string_boundaries: self.string_boundaries.space_used(),
+            dictionary: self.dictionary.token_data().len(),
+            token_boundaries: self.dictionary.token_boundaries.len() * std::mem::size_of::<u32>(),
+            heap_capacity: self.heap_size(),
+            peak_training_memory: self.dictionary.training_report().map(|report| report.peak_memory),
         }
+    }
 
-        size
+    /// Returns the number of tokens in the dictionary
+    #[inline]
+    pub fn num_tokens(&self) -> usize {
+        self.dictionary.num_tokens()
     }
 
-    /// Returns the total space (in bytes) used by the compressed data
-    pub fn space_used(&self) -> usize {
-        self.compressed_data.len() * std::mem::size_of::<u16>() + 
-        self.dictionary.len() + 
-        self.token_boundaries.len() * std::mem::size_of::<u32>()
+    /// Returns the bytes of the token with the given ID
+    ///
+    /// # Panics
+    /// Panics if `id` is not smaller than `num_tokens()`.
+    #[inline]
+    pub fn token_bytes(&self, id: T) -> &[u8] {
+        self.dictionary.token_bytes(id)
+    }
+
+    /// Serializes the compressed collection in the binary format described in [`format`]
+    ///
+    /// The output contains everything needed to decompress the collection: the
+    /// dictionary, the token boundaries, the string boundaries and the token stream.
+    ///
+    /// [`format`]: crate::compressor::format
+    pub fn serialize<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let header = Header {
+            threshold: self.config.threshold,
+            dictionary_len: self.dictionary.token_data().len(),
+            n_token_boundaries: self.dictionary.token_boundaries.len(),
+            n_string_boundaries: self.string_boundaries.len(),
+            n_tokens: self.compressed_data.n_tokens(),
+        };
+
+        header.write(&mut writer, S::VARIANT)?;
+        self.compressed_data.write_sections(
+            &mut writer,
+            self.dictionary.token_data(),
+            &self.dictionary.token_boundaries,
+            self.string_boundaries.iter(),
+        )
+    }
+
+    /// Deserializes a collection previously written by [`serialize`](Self::serialize)
+    ///
+    /// The input is fully validated, so corrupted or truncated data results in an
+    /// `InvalidData` or `UnexpectedEof` error rather than undefined behavior on decompression.
+    pub fn deserialize<R: Read>(mut reader: R) -> io::Result<Self> {
+        S::read_collection(&mut reader)
+    }
+
+    /// Assembles a collection from sections read and validated by the `format` module
+    pub(crate) fn from_sections(sections: Sections<S>) -> Self {
+        Self {
+            config: OnPairConfig::new(sections.threshold),
+            compressed_data: sections.compressed_data,
+            string_boundaries: StringBoundaries::from(sections.string_boundaries),
+            dictionary: OnPairDictionaryBase::from_parts(sections.threshold, sections.dictionary, sections.token_boundaries),
+        }
     }
 
     /// Shrinks all internal buffers to fit their current contents
     pub fn shrink_to_fit(&mut self) {
-        self.compressed_data.shrink_to_fit();
+        self.compressed_data.shrink();
         self.string_boundaries.shrink_to_fit();
         self.dictionary.shrink_to_fit();
-        self.token_boundaries.shrink_to_fit();
     }
 }
 
+impl<T: TokenId> OnPairBase<T> {
+    /// Returns the number of occurrences of each token in the compressed data, indexed by token ID
+    pub fn token_usage(&self) -> Vec<usize> {
+        stats::token_usage(&self.compressed_data, self.num_tokens())
+    }
+
+    /// Returns an iterator over the tokens as `(id, bytes, usage count)` triples, in ID order
+    ///
+    /// The usage counts are computed with one pass over the compressed data when this method is called.
+    pub fn tokens(&self) -> impl Iterator<Item = (T, &[u8], usize)> + '_ {
+        self.dictionary
+            .tokens()
+            .zip(self.token_usage())
+            .map(|((id, bytes), count)| (id, bytes, count))
+    }
+
+    /// Returns statistics on the collection and its dictionary, computed with one pass over the compressed data
+    pub fn stats(&self) -> CompressionStats {
+        stats::collect(&self.compressed_data, &self.string_boundaries, &self.dictionary.token_boundaries, self.space_used())
+    }
+
+    /// Returns the indices of the strings equal to `needle`, in increasing order
+    ///
+    /// Parsing is deterministic, so equal strings always have identical token IDs. The needle
+    /// is parsed once with the dictionary and compared with the token IDs of each string,
+    /// without decompressing them.
+    pub fn find_equal(&self, needle: &[u8]) -> impl Iterator<Item = usize> + '_ {
+        let needle = self.parse_needle(needle);
+        (0..self.len()).filter(move |&index| self.string_tokens(index) == needle)
+    }
+
+    /// Returns a bitmap of the strings equal to `needle` (see [`find_equal`](Self::find_equal))
+    pub fn eq_mask(&self, needle: &[u8]) -> Bitmap {
+        Bitmap::from_indices(self.len(), self.find_equal(needle))
+    }
+
+    /// Returns the indices of the strings starting with `prefix`, in increasing order
+    ///
+    /// Tokens that lie inside the prefix are compared whole with it, and only the token
+    /// crossing its end is compared partially, without decompressing any string.
+    pub fn starts_with<'a>(&'a self, prefix: &'a [u8]) -> impl Iterator<Item = usize> + 'a {
+        (0..self.len()).filter(move |&index| {
+            search::starts_with(self.string_tokens(index), &self.dictionary.bytes, &self.dictionary.token_boundaries, prefix)
+        })
+    }
+
+    /// Returns a bitmap of the strings starting with `prefix` (see [`starts_with`](Self::starts_with))
+    pub fn starts_with_mask(&self, prefix: &[u8]) -> Bitmap {
+        Bitmap::from_indices(self.len(), self.starts_with(prefix))
+    }
+
+    /// Returns the indices of the strings ending with `suffix`, in increasing order
+    ///
+    /// The tokens of each string are visited from the last one: tokens that lie inside the
+    /// suffix are compared whole with it, and only the token crossing its start is compared
+    /// partially, without decompressing any string.
+    pub fn ends_with<'a>(&'a self, suffix: &'a [u8]) -> impl Iterator<Item = usize> + 'a {
+        (0..self.len()).filter(move |&index| {
+            search::ends_with(self.string_tokens(index), &self.dictionary.bytes, &self.dictionary.token_boundaries, suffix)
+        })
+    }
+
+    /// Returns a bitmap of the strings ending with `suffix` (see [`ends_with`](Self::ends_with))
+    pub fn ends_with_mask(&self, suffix: &[u8]) -> Bitmap {
+        Bitmap::from_indices(self.len(), self.ends_with(suffix))
+    }
+
+    /// Returns the indices of the strings containing `pattern`, in increasing order
+    ///
+    /// See [`contains_any`](Self::contains_any).
+    pub fn contains(&self, pattern: &[u8]) -> impl Iterator<Item = usize> + '_ {
+        self.substring_matches(self.substring_automaton(&[pattern]))
+    }
+
+    /// Returns a bitmap of the strings containing `pattern` (see [`contains_any`](Self::contains_any))
+    pub fn contains_mask(&self, pattern: &[u8]) -> Bitmap {
+        Bitmap::from_indices(self.len(), self.contains(pattern))
+    }
+
+    /// Returns the indices of the strings containing at least one of `patterns`, in increasing order
+    ///
+    /// The patterns are compiled into an Aho–Corasick automaton, whose transitions over every
+    /// token of the dictionary are precomputed so that each token ID of a string advances it
+    /// with a single lookup, without decompressing any string. When the table would exceed
+    /// 2^22 transitions (states times tokens), the automaton walks the bytes of each token instead.
+    pub fn contains_any<P: AsRef<[u8]>>(&self, patterns: &[P]) -> impl Iterator<Item = usize> + '_ {
+        self.substring_matches(self.substring_automaton(patterns))
+    }
+
+    /// Returns one bitmap per pattern, of the strings containing it (see [`contains_any`](Self::contains_any))
+    pub fn contains_masks<P: AsRef<[u8]>>(&self, patterns: &[P]) -> Vec<Bitmap> {
+        let automaton = self.substring_automaton(patterns);
+        let mut masks = vec![Bitmap::new(self.len()); patterns.len()];
+        for index in 0..self.len() {
+            let token_ids = self.string_tokens(index);
+            automaton.for_each_match(token_ids, &self.dictionary.bytes, &self.dictionary.token_boundaries, |pattern| {
+                masks[pattern].insert(index)
+            });
+        }
+        masks
+    }
+
+    /// Returns the indices of the strings matched by the regular expression `pattern`, in increasing order
+    ///
+    /// A string matches if the expression matches anywhere in it. The expression is compiled
+    /// into a DFA, whose transitions over every token of the dictionary are precomputed so
+    /// that each token ID of a string advances it with a single lookup. When the table would
+    /// exceed 2^22 transitions (states times tokens), the DFA runs over the bytes of each token
+    /// instead. Expressions whose DFA would exceed 16 MiB run on a lazy DFA, which only
+    /// determinizes the states the strings reach.
+    ///
+    /// Returns an error if the expression is invalid or its DFA cannot be built.
+    #[cfg(feature = "regex")]
+    pub fn regex_matches(&self, pattern: &str) -> Result<impl Iterator<Item = usize> + '_, OnPairError> {
+        let mut automaton = RegexAutomaton::new(pattern, &self.dictionary.bytes, &self.dictionary.token_boundaries)?;
+        Ok((0..self.len()).filter(move |&index| {
+            automaton.is_match(self.string_tokens(index), &self.dictionary.bytes, &self.dictionary.token_boundaries)
+        }))
+    }
+
+    /// Returns a bitmap of the strings matched by the regular expression `pattern`
+    /// (see [`regex_matches`](Self::regex_matches))
+    #[cfg(feature = "regex")]
+    pub fn regex_mask(&self, pattern: &str) -> Result<Bitmap, OnPairError> {
+        Ok(Bitmap::from_indices(self.len(), self.regex_matches(pattern)?))
+    }
+
+    /// Compiles substring patterns into an automaton over the dictionary tokens
+    fn substring_automaton<P: AsRef<[u8]>>(&self, patterns: &[P]) -> SubstringAutomaton {
+        SubstringAutomaton::new(patterns, &self.dictionary.bytes, &self.dictionary.token_boundaries)
+    }
+
+    /// Returns the indices of the strings containing at least one pattern of `automaton`
+    fn substring_matches(&self, automaton: SubstringAutomaton) -> impl Iterator<Item = usize> + '_ {
+        (0..self.len()).filter(move |&index| {
+            automaton.matches_any(self.string_tokens(index), &self.dictionary.bytes, &self.dictionary.token_boundaries)
+        })
+    }
+
+    /// Returns the token IDs of a specific string
+    #[inline]
+    fn string_tokens(&self, index: usize) -> &[T] {
+        &self.compressed_data[self.string_boundaries.range(index)]
+    }
+
+    /// Parses a string that is not part of the collection with the dictionary
+    ///
+    /// An empty collection may have no dictionary, in which case no token IDs are returned.
+    fn parse_needle(&self, needle: &[u8]) -> Vec<T> {
+        let mut token_ids = Vec::new();
+        if !self.is_empty() {
+            self.dictionary.encode(needle, &[0, needle.len()], &mut token_ids, &mut Vec::new());
+        }
+        token_ids
+    }
+}
+
+impl<T: TokenId, S: TokenStorage<T>, A: AsRef<[u8]>> Extend<A> for OnPairBase<T, S> {
+    /// Appends strings, encoding them with the current dictionary
+    ///
+    /// # Panics
+    /// Panics if no dictionary has been trained or provided, unless there are no strings.
+    fn extend<I: IntoIterator<Item = A>>(&mut self, strings: I) {
+        // The strings are flattened so that they are parsed in one batch, like `extend_bytes`
+        let (data, end_positions) = flatten_bytes(strings);
+        if end_positions.len() > 1 {
+            self.extend_bytes(&data, &end_positions);
+        }
+    }
+}
+
+/// Decodes a slice of token IDs into `buffer`, returning the number of bytes written
+///
+/// # Safety
+/// See [`decode_token_ids`].
+#[inline(always)]
+pub(crate) unsafe fn decode_tokens<T: TokenId>(tokens: &[T], dictionary: *const u8, token_boundaries: &[u32], buffer: *mut u8) -> usize {
+    decode_token_ids(tokens.iter().copied(), dictionary, token_boundaries, buffer)
+}
+
+/// Decodes a sequence of token IDs into `buffer`, returning the number of bytes written
+///
+/// For each token, copies 16 bytes regardless of the actual token length, then copies
+/// any remaining bytes if the token is longer than 16 bytes.
+///
+/// # Safety
+/// Every token ID must index a token in `token_boundaries`, 16 bytes must be readable
+/// from the start of every token in `dictionary`, and `buffer` must have room for the
+/// decoded bytes plus the 16-byte copy of the last token.
+#[inline(always)]
+unsafe fn decode_token_ids<T: TokenId>(
+    token_ids: impl Iterator<Item = T>,
+    dictionary: *const u8,
+    token_boundaries: &[u32],
+    buffer: *mut u8,
+) -> usize {
+    let end_positions_ptr = token_boundaries.as_ptr();
+    let mut size = 0;
+
+    for token_id in token_ids {
+        let dict_start = *end_positions_ptr.add(token_id.index()) as usize;
+        let dict_end = *end_positions_ptr.add(token_id.index() + 1) as usize;
+        let length = dict_end - dict_start;
+
+        let mut src = dictionary.add(dict_start);
+        let mut dst = buffer.add(size);
+        std::ptr::copy_nonoverlapping(src, dst, FAST_COPY_SIZE);
+
+        if length > FAST_COPY_SIZE {
+            src = src.add(FAST_COPY_SIZE);
+            dst = dst.add(FAST_COPY_SIZE);
+            std::ptr::copy_nonoverlapping(src, dst, length - FAST_COPY_SIZE);
+        }
+
+        size += length;
+    }
+
+    size
+}
+
+/// Returns the total length in bytes of a sequence of token IDs once decoded
+#[inline]
+pub(crate) fn decoded_len<T: TokenId>(token_ids: impl IntoIterator<Item = T>, token_boundaries: &[u32]) -> usize {
+    token_ids
+        .into_iter()
+        .map(|token_id| (token_boundaries[token_id.index() + 1] - token_boundaries[token_id.index()]) as usize)
+        .sum()
+}
+
 /// Flattens a collection of strings into a single byte array with boundary positions
 /// 
 /// Returns a tuple of (flattened_data, end_positions) where end_positions is a 
 /// prefix sum array starting with 0.
-fn flatten_strings<S: AsRef<str>>(strings: &[S]) -> (Vec<u8>, Vec<usize>) {
+pub(crate) fn flatten_strings<S: AsRef<str>>(strings: &[S]) -> (Vec<u8>, Vec<usize>) {
     let total_len: usize = strings.iter().map(|s| s.as_ref().len()).sum();
     let mut data = Vec::with_capacity(total_len);
     let mut end_positions = Vec::with_capacity(strings.len() + 1);
@@ -280,4 +690,195 @@ fn flatten_strings<S: AsRef<str>>(strings: &[S]) -> (Vec<u8>, Vec<usize>) {
     }
     
     (data, end_positions)
-}
\ No newline at end of file
+}
+
+/// Flattens byte strings of unknown count into a single byte array with boundary positions
+///
+/// Returns a tuple of (flattened_data, end_positions) like [`flatten_strings`].
+pub(crate) fn flatten_bytes<S: AsRef<[u8]>>(strings: impl IntoIterator<Item = S>) -> (Vec<u8>, Vec<usize>) {
+    let mut data = Vec::new();
+    let mut end_positions = vec![0];
+
+    for string in strings {
+        data.extend_from_slice(string.as_ref());
+        end_positions.push(data.len());
+    }
+
+    (data, end_positions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compressor::packed::PackedTokens;
+    use crate::compressor::testing::{self, assert_strings, serialized, Collection};
+    use crate::compressor::{CompressedStrings, OnPair16};
+
+    fn check_push<T: TokenId, S: TokenStorage<T>>()
+    where
+        OnPairBase<T, S>: Collection,
+    {
+        let strings = testing::urls(300, 1);
+        let (head, tail) = strings.split_at(200);
+
+        // After compress_bytes
+        let mut compressor = OnPairBase::<T, S>::with_config(OnPairConfig::new(3).with_seed(1));
+        compressor.compress_strings(head);
+        compressor.push_str(&tail[0]);
+        compressor.push_bytes(b"");
+        compressor.extend(&tail[1..]);
+
+        let mut expected = head.to_vec();
+        expected.push(tail[0].clone());
+        expected.push(String::new());
+        expected.extend_from_slice(&tail[1..]);
+        assert_strings(&compressor, &expected);
+
+        // After with_dictionary
+        let (data, end_positions) = flatten_strings(head);
+        let dictionary = OnPairDictionaryBase::<T>::train(&data, &end_positions, 3);
+        let mut compressor = OnPairBase::<T, S>::with_dictionary(dictionary);
+        compressor.extend(&strings);
+        assert_strings(&compressor, &strings);
+
+        // After deserialize, and serialized again
+        let mut compressor = OnPairBase::<T, S>::deserialize(&serialized(&compressor)[..]).unwrap();
+        compressor.extend(tail);
+        compressor.extend(Vec::<&str>::new());
+
+        let expected = [&strings[..], tail].concat();
+        assert_strings(&compressor, &expected);
+        assert_strings(&OnPairBase::<T, S>::deserialize(&serialized(&compressor)[..]).unwrap(), &expected);
+    }
+
+    #[test]
+    fn push_after_compress_with_dictionary_and_deserialize() {
+        check_push::<u16, Vec<u16>>();
+        check_push::<u32, Vec<u32>>();
+        check_push::<u16, PackedTokens>();
+    }
+
+    fn check_extend_is_one_batch<T: TokenId, S: TokenStorage<T>>()
+    where
+        OnPairBase<T, S>: Collection,
+    {
+        let strings = testing::urls(200, 2);
+        let (data, end_positions) = flatten_strings(&strings);
+        let dictionary = OnPairDictionaryBase::<T>::train(&data, &end_positions, 3);
+
+        let mut extended = OnPairBase::<T, S>::with_dictionary(dictionary.clone());
+        extended.extend(&strings);
+        let mut batched = OnPairBase::<T, S>::with_dictionary(dictionary);
+        batched.extend_bytes(&data, &end_positions);
+
+        assert_eq!(serialized(&extended), serialized(&batched));
+    }
+
+    #[test]
+    fn extend_matches_extend_bytes() {
+        check_extend_is_one_batch::<u16, Vec<u16>>();
+        check_extend_is_one_batch::<u32, Vec<u32>>();
+        check_extend_is_one_batch::<u16, PackedTokens>();
+    }
+
+    fn check_missing_dictionary<T: TokenId, S: TokenStorage<T>>() {
+        let mut compressor = OnPairBase::<T, S>::new(3);
+        assert_eq!(compressor.try_extend_bytes(b"abc", &[0, 3]), Err(OnPairError::MissingDictionary));
+        assert_eq!(compressor.try_extend_bytes(b"", &[0]), Err(OnPairError::MissingDictionary));
+
+        // Appending no strings needs no dictionary
+        compressor.extend(Vec::<&[u8]>::new());
+        assert!(compressor.is_empty());
+    }
+
+    #[test]
+    fn append_without_dictionary() {
+        check_missing_dictionary::<u16, Vec<u16>>();
+        check_missing_dictionary::<u32, Vec<u32>>();
+        check_missing_dictionary::<u16, PackedTokens>();
+    }
+
+    #[test]
+    #[should_panic(expected = "without a trained dictionary")]
+    fn push_without_dictionary() {
+        OnPair::new(3).push_str("abc");
+    }
+
+    /// Checks `find_equal` and `eq_mask` against decompressing and comparing every string
+    fn check_find_equal<C: CompressedStrings>(
+        collection: &C,
+        needles: &[&[u8]],
+        find_equal: impl Fn(&[u8]) -> Vec<usize>,
+        eq_mask: impl Fn(&[u8]) -> Bitmap,
+    ) {
+        for &needle in needles {
+            let expected = (0..collection.len()).filter(|&index| collection.get(index) == needle).collect::<Vec<_>>();
+            assert_eq!(find_equal(needle), expected, "{:?}", needle);
+            assert_eq!(eq_mask(needle), Bitmap::from_indices(collection.len(), expected), "{:?}", needle);
+        }
+    }
+
+    #[test]
+    fn find_equal_matches_decompressed_strings() {
+        let mut strings = testing::urls(300, 3);
+        strings.extend([String::new(), strings[0].clone(), String::new(), strings[0].clone(), strings[1].clone()]);
+        let config = OnPairConfig::new(3).with_seed(3);
+        let onpair: OnPair = testing::compressed(&strings, config);
+        let onpair16: OnPair16 = testing::compressed(&strings, config);
+
+        let repeated = strings[0].as_bytes();
+        let extended = [strings[2].as_bytes(), b"0"].concat();
+        let needles = [
+            repeated,
+            strings[2].as_bytes(),
+            b"",
+            b"https://absent.example.net/",
+            &repeated[..repeated.len() - 1],
+            &extended,
+            b"https://",
+        ];
+        assert!(!strings.iter().any(|string| string.as_bytes() == &repeated[..repeated.len() - 1]));
+
+        check_find_equal(&onpair, &needles, |needle| onpair.find_equal(needle).collect(), |needle| onpair.eq_mask(needle));
+        check_find_equal(&onpair16, &needles, |needle| onpair16.find_equal(needle).collect(), |needle| onpair16.eq_mask(needle));
+        assert_eq!(onpair.find_equal(repeated).count(), 3);
+        assert_eq!(onpair.find_equal(b"").count(), 2);
+    }
+
+    #[test]
+    fn find_equal_on_empty_collection() {
+        let onpair = OnPair::new(3);
+        assert_eq!(onpair.find_equal(b"").count(), 0);
+        assert_eq!(onpair.eq_mask(b"abc"), Bitmap::new(0));
+        let onpair16 = OnPair16::new(3);
+        assert_eq!(onpair16.find_equal(b"abc").count(), 0);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn onpair32_beyond_u16_token_ids() {
+        use crate::compressor::OnPair32View;
+        use rand::rngs::StdRng;
+        use rand::{Rng, SeedableRng};
+
+        // Random lowercase strings have too many distinct substrings for a 65,536-token dictionary
+        let mut rng = StdRng::seed_from_u64(16);
+        let strings: Vec<String> = (0..20_000)
+            .map(|_| (0..rng.gen_range(0..200)).map(|_| rng.gen_range(b'a'..=b'z') as char).collect())
+            .collect();
+
+        let config = OnPairConfig::new(2).with_seed(16).with_max_tokens(70_000);
+        let compressor = testing::compressed::<OnPair32>(&strings, config);
+        assert!(compressor.num_tokens() > 1 << 16);
+        assert!(compressor.compressed_data.iter().any(|&token_id| token_id >= 1 << 16));
+        assert_strings(&compressor, &strings);
+
+        let bytes = serialized(&compressor);
+        let deserialized = OnPair32::deserialize(&bytes[..]).unwrap();
+        assert_eq!(deserialized.num_tokens(), compressor.num_tokens());
+        assert_strings(&deserialized, &strings);
+
+        let words = testing::aligned(&bytes);
+        assert_strings(&OnPair32View::new(testing::blob(&words, bytes.len())).unwrap(), &strings);
+    }
+}
@@ -0,0 +1,75 @@
+use crate::compressor::onpair::OnPairBase;
+use crate::compressor::packed::PackedTokens;
+
+/// OnPair collection storing token IDs as bit-packed codes
+///
+/// The dictionary is an ordinary `OnPairDictionary`; once it is trained, every token ID
+/// is stored with just enough bits to address its tokens, e.g. 9 bits for a dictionary
+/// of at most 512 tokens. Columns with few distinct values never fill a 65,536-token
+/// dictionary, so their codes are much narrower than 16 bits. `with_max_tokens(1 << k)` in
+/// the configuration bounds the codes to `k` bits.
+///
+/// Every dictionary contains the 256 single-byte tokens, so codes are 8 bits wide only
+/// when no pair is merged, and at least 9 bits wide otherwise.
+///
+/// Training, parsing and decoding are shared with `OnPair`; only the storage of the
+/// token stream differs.
+pub type OnPairPacked = OnPairBase<u16, PackedTokens>;
+
+impl OnPairPacked {
+    /// Returns the number of bits used to store each token ID
+    #[inline]
+    pub fn code_width(&self) -> u32 {
+        self.compressed_data.width()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compressor::packed::code_width;
+    use crate::compressor::testing::{self, assert_strings, compressed};
+    use crate::compressor::{OnPair, OnPairConfig};
+
+    fn check_matches_onpair(strings: &[String], config: OnPairConfig) {
+        let onpair = compressed::<OnPair>(strings, config);
+        let packed = compressed::<OnPairPacked>(strings, config);
+
+        assert_eq!(packed.num_tokens(), onpair.num_tokens());
+        assert_eq!(packed.code_width(), code_width(packed.num_tokens()));
+        if let Some(max_tokens) = config.max_tokens() {
+            assert!(packed.code_width() <= code_width(max_tokens), "{} tokens", max_tokens);
+        }
+        let (mut expected, mut decompressed) = (Vec::new(), Vec::new());
+        onpair.decompress_all_into(&mut expected);
+        packed.decompress_all_into(&mut decompressed);
+        assert_eq!(decompressed, expected);
+        assert_strings(&packed, strings);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn packed_matches_onpair() {
+        let mut strings = testing::urls(1000, 17);
+        strings[3].clear();
+        let config = OnPairConfig::new(3).with_seed(17);
+        for max_tokens in [256, 257, 300, 512, 513, 1024] {
+            check_matches_onpair(&strings, config.with_max_tokens(max_tokens));
+        }
+        check_matches_onpair(&strings, config);
+        check_matches_onpair(&[], config);
+
+        // A dictionary limited to the single-byte tokens stores 8-bit codes
+        let packed = compressed::<OnPairPacked>(&strings, config.with_max_tokens(256));
+        assert_eq!(packed.code_width(), 8);
+    }
+
+    #[test]
+    fn low_cardinality_codes_are_narrow() {
+        let values = ["OK", "Not Found", "Internal Server Error", "Bad Request"];
+        let strings: Vec<String> = (0..2000).map(|index| values[index * 7 % values.len()].to_owned()).collect();
+        let packed = compressed::<OnPairPacked>(&strings, OnPairConfig::new(3).with_seed(17));
+        assert!(packed.code_width() < 16);
+        assert_strings(&packed, &strings);
+    }
+}
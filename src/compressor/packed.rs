@@ -0,0 +1,215 @@
+//! Bit-packed storage for token IDs
+//!
+//! Token IDs are stored back to back as codes of a fixed number of bits, least
+//! significant bit first. The storage keeps 8 zero bytes after the last code, so
+//! every code is read or written with a single unaligned 64-bit access.
+
+use std::ops::Range;
+
+/// Zero bytes kept after the last code
+const SLACK: usize = 8;
+
+/// Maximum code width, matching the 16-bit IDs of `OnPairDictionary`
+pub(crate) const MAX_WIDTH: u32 = 16;
+
+/// Returns the number of bits needed to store the token IDs of a dictionary with `n_tokens` tokens
+#[inline]
+pub(crate) fn code_width(n_tokens: usize) -> u32 {
+    usize::BITS - n_tokens.saturating_sub(1).leading_zeros()
+}
+
+/// Returns the number of bytes occupied by `n_codes` codes of `width` bits
+#[inline]
+fn packed_len(n_codes: usize, width: u32) -> usize {
+    (n_codes * width as usize).div_ceil(8)
+}
+
+/// Token IDs packed at a fixed code width, the token storage of `OnPairPacked`
+#[derive(Clone, Debug)]
+pub struct PackedTokens {
+    bytes: Vec<u8>,     // Packed codes, followed by SLACK zero bytes
+    width: u32,         // Bits per code
+    len: usize,         // Number of codes
+}
+
+impl PackedTokens {
+    /// Creates an empty sequence of codes of `width` bits
+    pub(crate) fn new(width: u32) -> Self {
+        assert!(width <= MAX_WIDTH, "Code width must be at most {} bits", MAX_WIDTH);
+        Self { bytes: vec![0; SLACK], width, len: 0 }
+    }
+
+    /// Creates a sequence of `len` codes of `width` bits from their packed bytes
+    pub(crate) fn from_bytes(mut bytes: Vec<u8>, width: u32, len: usize) -> Self {
+        assert!(width <= MAX_WIDTH, "Code width must be at most {} bits", MAX_WIDTH);
+        bytes.truncate(packed_len(len, width));
+        bytes.resize(packed_len(len, width) + SLACK, 0);
+        Self { bytes, width, len }
+    }
+
+    /// Returns the number of codes
+    #[inline]
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns the number of bits per code
+    #[inline]
+    pub(crate) fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Returns the packed codes, without the trailing slack
+    #[inline]
+    pub(crate) fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.bytes.len() - SLACK]
+    }
+
+    /// Removes every code and switches to codes of `width` bits
+    pub(crate) fn reset(&mut self, width: u32) {
+        assert!(width <= MAX_WIDTH, "Code width must be at most {} bits", MAX_WIDTH);
+        self.bytes.clear();
+        self.bytes.resize(SLACK, 0);
+        self.width = width;
+        self.len = 0;
+    }
+
+    /// Reserves room for at least `additional` more codes
+    pub(crate) fn reserve(&mut self, additional: usize) {
+        self.bytes.reserve(packed_len(additional, self.width));
+    }
+
+    /// Appends token IDs, each of which must fit in the code width
+    pub(crate) fn extend_from_slice(&mut self, token_ids: &[u16]) {
+        let width = self.width as usize;
+        self.bytes.resize(packed_len(self.len + token_ids.len(), self.width) + SLACK, 0);
+
+        for &token_id in token_ids {
+            debug_assert!((token_id as u64) < 1 << width);
+            let bit = self.len * width;
+            let offset = bit / 8;
+
+            // The codes after the current one are still zero, so OR-ing the code in is enough
+            let mut word = [0u8; 8];
+            word.copy_from_slice(&self.bytes[offset..offset + 8]);
+            let word = u64::from_le_bytes(word) | (token_id as u64) << (bit % 8);
+            self.bytes[offset..offset + 8].copy_from_slice(&word.to_le_bytes());
+
+            self.len += 1;
+        }
+    }
+
+    /// Returns the token ID at `index` without bounds checking
+    ///
+    /// # Safety
+    /// `index` must be smaller than `len()`.
+    #[inline(always)]
+    pub(crate) unsafe fn get_unchecked(&self, index: usize) -> u16 {
+        let bit = index * self.width as usize;
+        let word = u64::from_le(self.bytes.as_ptr().add(bit / 8).cast::<u64>().read_unaligned());
+        let mask = (1u32 << self.width) - 1;
+        (word >> (bit % 8)) as u16 & mask as u16
+    }
+
+    /// Returns an iterator over the token IDs in `range`
+    #[inline]
+    pub(crate) fn iter(&self, range: Range<usize>) -> impl Iterator<Item = u16> + '_ {
+        assert!(range.start <= range.end && range.end <= self.len, "Code range out of bounds");
+        range.map(move |index| unsafe { self.get_unchecked(index) })
+    }
+
+    /// Returns the heap memory (in bytes) allocated for the codes, including spare capacity and slack
+    pub(crate) fn heap_size(&self) -> usize {
+        self.bytes.capacity()
+    }
+
+    /// Shrinks the storage to fit its current contents
+    pub(crate) fn shrink_to_fit(&mut self) {
+        self.bytes.shrink_to_fit();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns `n` codes of `width` bits, ending with the largest code so that the last
+    /// write and read touch the slack
+    fn codes(n: usize, width: u32) -> Vec<u16> {
+        let max = ((1u32 << width) - 1) as u16;
+        let mut state = width as u64 + 1;
+        let mut codes: Vec<u16> = (0..n)
+            .map(|_| {
+                state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                (state >> 48) as u16 & max
+            })
+            .collect();
+        if let Some(last) = codes.last_mut() {
+            *last = max;
+        }
+        codes
+    }
+
+    fn check(packed: &PackedTokens, codes: &[u16]) {
+        assert_eq!(packed.len(), codes.len());
+        assert_eq!(packed.as_bytes().len(), packed_len(codes.len(), packed.width()));
+        assert_eq!(packed.bytes[packed.bytes.len() - SLACK..], [0; SLACK]);
+        assert!(packed.iter(0..codes.len()).eq(codes.iter().copied()));
+        for (index, &code) in codes.iter().enumerate() {
+            assert_eq!(unsafe { packed.get_unchecked(index) }, code, "code {}", index);
+        }
+        if let Some(middle) = codes.len().checked_sub(1).map(|last| last / 2) {
+            assert!(packed.iter(middle..codes.len()).eq(codes[middle..].iter().copied()));
+        }
+    }
+
+    #[test]
+    fn roundtrip_at_every_width() {
+        for width in 0..=MAX_WIDTH {
+            for n in [0, 1, 2, 7, 8, 9, 63, 64, 65, 500] {
+                let codes = codes(n, width);
+
+                let mut packed = PackedTokens::new(width);
+                packed.extend_from_slice(&codes);
+                check(&packed, &codes);
+
+                // Appending in several slices packs the codes the same way
+                let mut appended = PackedTokens::new(width);
+                for chunk in codes.chunks(3) {
+                    appended.extend_from_slice(chunk);
+                }
+                check(&appended, &codes);
+                assert_eq!(appended.as_bytes(), packed.as_bytes());
+
+                let restored = PackedTokens::from_bytes(packed.as_bytes().to_vec(), width, n);
+                check(&restored, &codes);
+
+                // Extra bytes beyond the codes are dropped
+                let mut bytes = packed.as_bytes().to_vec();
+                bytes.extend([0xff; 3]);
+                check(&PackedTokens::from_bytes(bytes, width, n), &codes);
+
+                let codes = self::codes(n, MAX_WIDTH - width);
+                packed.reset(MAX_WIDTH - width);
+                check(&packed, &[]);
+                packed.extend_from_slice(&codes);
+                check(&packed, &codes);
+            }
+        }
+    }
+
+    #[test]
+    fn widths_of_dictionary_sizes() {
+        for (n_tokens, width) in [(0, 0), (1, 0), (2, 1), (256, 8), (257, 9), (512, 9), (513, 10), (1 << 16, 16)] {
+            assert_eq!(code_width(n_tokens), width, "{} tokens", n_tokens);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Code range out of bounds")]
+    fn iter_out_of_bounds() {
+        let mut packed = PackedTokens::new(9);
+        packed.extend_from_slice(&[1, 2, 3]);
+        packed.iter(1..4).count();
+    }
+}
@@ -0,0 +1,406 @@
+//! Multi-threaded parsing and training phases (requires the `parallel` feature)
+//!
+//! Strings are parsed independently against a read-only matcher, so the input is
+//! split into chunks of consecutive strings that are parsed concurrently on the
+//! rayon thread pool and then stitched back together in order. The output is
+//! identical to the sequential parse.
+//!
+//! Training is inherently sequential, since every merge changes how the following
+//! strings are parsed. The parallel variant processes the training order in
+//! batches: workers count adjacent token pairs on disjoint shards of a batch with a
+//! shared snapshot of the matcher, the counts are summed, and the pairs that reached
+//! the threshold are merged before the next batch starts.
+
+use crate::compressor::config::OnPairConfig;
+use crate::compressor::sample::TrainingReport;
+use crate::compressor::token::TokenId;
+use crate::lpm::{hash_map_heap_size, LongestPrefixMatcher, LongestPrefixMatcher16};
+use rayon::prelude::*;
+use rustc_hash::FxHashMap;
+
+/// Minimum number of strings per chunk, so that small inputs are not split needlessly
+const MIN_CHUNK_STRINGS: usize = 4096;
+
+/// Chunks per thread, to balance the load when string lengths vary
+const CHUNKS_PER_THREAD: usize = 4;
+
+/// Parses the strings described by `end_positions` in parallel with the sequential `encode`
+///
+/// `encode` has the contract of the dictionaries' `encode` methods: it appends the token IDs
+/// of each string to its first vector and the end position of each string to its second
+/// vector, preceded by a leading boundary if that vector is empty.
+pub(crate) fn encode<T, F>(end_positions: &[usize], compressed_data: &mut Vec<T>, string_boundaries: &mut Vec<usize>, encode: F)
+where
+    T: TokenId,
+    F: Fn(&[usize], &mut Vec<T>, &mut Vec<usize>) + Sync,
+{
+    let n_strings = end_positions.len().saturating_sub(1);
+    let n_chunks = rayon::current_num_threads() * CHUNKS_PER_THREAD;
+    let chunk_len = n_strings.div_ceil(n_chunks).max(MIN_CHUNK_STRINGS);
+
+    if n_strings <= chunk_len {
+        encode(end_positions, compressed_data, string_boundaries);
+        return;
+    }
+
+    // Each chunk covers strings `start..start + chunk_len`, sharing its first and last end
+    // positions with the neighbouring chunks
+    let chunks: Vec<(Vec<T>, Vec<usize>)> = (0..n_strings)
+        .step_by(chunk_len)
+        .collect::<Vec<_>>()
+        .into_par_iter()
+        .map(|start| {
+            let end = (start + chunk_len).min(n_strings);
+            let mut chunk_data = Vec::new();
+            let mut chunk_boundaries = Vec::with_capacity(end - start + 1);
+            encode(&end_positions[start..=end], &mut chunk_data, &mut chunk_boundaries);
+            (chunk_data, chunk_boundaries)
+        })
+        .collect();
+
+    if string_boundaries.is_empty() {
+        string_boundaries.push(compressed_data.len());
+    }
+
+    compressed_data.reserve(chunks.iter().map(|(chunk_data, _)| chunk_data.len()).sum());
+    string_boundaries.reserve(n_strings);
+
+    for (chunk_data, chunk_boundaries) in chunks {
+        // Chunk boundaries are relative to the chunk and start with a leading 0
+        let offset = compressed_data.len();
+        string_boundaries.extend(chunk_boundaries[1..].iter().map(|&boundary| boundary + offset));
+        compressed_data.extend_from_slice(&chunk_data);
+    }
+}
+
+/// Dynamic matcher that tokens are added to during training
+pub(crate) trait TrainingMatcher<V>: Sync {
+    /// Finds the longest token that is a prefix of `data`
+    fn find_longest_match(&self, data: &[u8]) -> Option<(V, usize)>;
+
+    /// Inserts a token, returning `false` if the matcher rejected it
+    fn insert(&mut self, token: &[u8], id: V) -> bool;
+
+    /// Returns an estimate of the heap memory (in bytes) allocated by the matcher
+    fn heap_size(&self) -> usize;
+}
+
+impl<V: TokenId> TrainingMatcher<V> for LongestPrefixMatcher<V> {
+    #[inline]
+    fn find_longest_match(&self, data: &[u8]) -> Option<(V, usize)> {
+        LongestPrefixMatcher::find_longest_match(self, data)
+    }
+
+    #[inline]
+    fn insert(&mut self, token: &[u8], id: V) -> bool {
+        LongestPrefixMatcher::insert(self, token, id);
+        true
+    }
+
+    fn heap_size(&self) -> usize {
+        LongestPrefixMatcher::heap_size(self)
+    }
+}
+
+impl TrainingMatcher<u16> for LongestPrefixMatcher16 {
+    #[inline]
+    fn find_longest_match(&self, data: &[u8]) -> Option<(u16, usize)> {
+        LongestPrefixMatcher16::find_longest_match(self, data)
+    }
+
+    #[inline]
+    fn insert(&mut self, token: &[u8], id: u16) -> bool {
+        LongestPrefixMatcher16::insert(self, token, id)
+    }
+
+    fn heap_size(&self) -> usize {
+        LongestPrefixMatcher16::heap_size(self)
+    }
+}
+
+/// Dictionary under construction: the matcher and the token storage it mirrors
+pub(crate) struct Tokens<'a, M> {
+    pub(crate) lpm: &'a mut M,
+    pub(crate) bytes: &'a mut Vec<u8>,
+    pub(crate) token_boundaries: &'a mut Vec<u32>,
+}
+
+/// Effective limits of the dictionary under construction
+#[derive(Clone, Copy)]
+pub(crate) struct Limits {
+    pub(crate) max_length: usize,
+    pub(crate) max_tokens: usize,
+}
+
+/// Trains on the strings of `order` in batches of `config.parallel_batch` strings
+///
+/// Pairs whose merged token would exceed `limits.max_length` bytes are not counted. Within a
+/// batch the pairs that reached the threshold are merged by decreasing frequency (ties broken
+/// by token IDs), which makes the result independent of the number of threads.
+pub(crate) fn train<V: TokenId, M: TrainingMatcher<V>>(
+    data: &[u8],
+    end_positions: &[usize],
+    order: &[usize],
+    config: &OnPairConfig,
+    limits: Limits,
+    tokens: Tokens<'_, M>,
+    report: &mut TrainingReport,
+) {
+    let Tokens { lpm, bytes, token_boundaries } = tokens;
+    let Limits { max_length, max_tokens } = limits;
+    let batch_size = config.parallel_batch.expect("parallel training requires a batch size");
+    let threshold = config.threshold as u32;
+
+    let mut frequency: FxHashMap<(V, V), u32> = FxHashMap::default();
+    let mut next_token_id = token_boundaries.len() - 1;
+
+    for batch in order.chunks(batch_size) {
+        // Phase 1: Count adjacent pairs on disjoint shards with a snapshot of the matcher
+        let snapshot = &*lpm;
+        let shard_len = batch.len().div_ceil(rayon::current_num_threads());
+        let batch_frequency = batch
+            .par_chunks(shard_len)
+            .map(|shard| count_pairs(data, end_positions, shard, snapshot, max_length))
+            .reduce(FxHashMap::default, merge_counts);
+
+        // The batch counts coexist with the accumulated counts until they are folded in
+        let memory = hash_map_heap_size(&frequency) +
+            hash_map_heap_size(&batch_frequency) +
+            lpm.heap_size() +
+            bytes.capacity() +
+            token_boundaries.capacity() * std::mem::size_of::<u32>();
+        report.peak_memory = report.peak_memory.max(memory);
+
+        report.trained_strings += batch.len();
+        report.trained_bytes += batch.iter().map(|&index| end_positions[index + 1] - end_positions[index]).sum::<usize>();
+
+        // Phase 2: Accumulate the counts and collect the pairs that reached the threshold
+        let mut frequent_pairs = Vec::new();
+        for (pair, count) in batch_frequency {
+            let total = frequency.entry(pair).or_insert(0);
+            *total += count;
+            if *total >= threshold {
+                frequent_pairs.push((*total, pair));
+            }
+        }
+        frequent_pairs.sort_unstable_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+
+        // Phase 3: Merge the frequent pairs into new tokens
+        for (_, (left, right)) in frequent_pairs {
+            frequency.remove(&(left, right));
+
+            let token = |id: V| {
+                let id = id.index();
+                token_boundaries[id] as usize..token_boundaries[id + 1] as usize
+            };
+            let mut merged_token = bytes[token(left)].to_vec();
+            merged_token.extend_from_slice(&bytes[token(right)]);
+
+            // Token boundaries are 32-bit offsets, which caps the size of the token data
+            if bytes.len() + merged_token.len() > u32::MAX as usize {
+                report.dictionary_full = true;
+                return;
+            }
+
+            // Pairs counted against the same snapshot can spell the same token
+            let exists = lpm
+                .find_longest_match(&merged_token)
+                .is_some_and(|(_, length)| length == merged_token.len());
+            if exists || !lpm.insert(&merged_token, V::from_index(next_token_id)) {
+                continue;
+            }
+
+            bytes.extend_from_slice(&merged_token);
+            token_boundaries.push(bytes.len() as u32);
+            next_token_id += 1;
+
+            if next_token_id == max_tokens {
+                report.dictionary_full = true;
+                return;
+            }
+        }
+    }
+}
+
+/// Counts the adjacent token pairs in the greedy parse of the given strings
+fn count_pairs<V: TokenId, M: TrainingMatcher<V>>(
+    data: &[u8],
+    end_positions: &[usize],
+    shard: &[usize],
+    lpm: &M,
+    max_length: usize,
+) -> FxHashMap<(V, V), u32> {
+    let mut frequency = FxHashMap::default();
+
+    for &index in shard {
+        let start = end_positions[index];
+        let end = end_positions[index + 1];
+
+        let mut previous: Option<(V, usize)> = None;
+        let mut pos = start;
+        while pos < end {
+            let (token_id, length) = lpm
+                .find_longest_match(&data[pos..end])
+                .expect("single-byte tokens always match");
+            if let Some((previous_token_id, previous_length)) = previous {
+                if previous_length + length <= max_length {
+                    *frequency.entry((previous_token_id, token_id)).or_insert(0) += 1;
+                }
+            }
+            previous = Some((token_id, length));
+            pos += length;
+        }
+    }
+
+    frequency
+}
+
+/// Sums two pair count tables, folding the smaller one into the larger one
+fn merge_counts<V: TokenId>(
+    mut a: FxHashMap<(V, V), u32>,
+    mut b: FxHashMap<(V, V), u32>,
+) -> FxHashMap<(V, V), u32> {
+    if a.len() < b.len() {
+        std::mem::swap(&mut a, &mut b);
+    }
+    for (pair, count) in b {
+        *a.entry(pair).or_insert(0) += count;
+    }
+    a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compressor::format::{self, Header};
+    use crate::compressor::onpair::flatten_strings;
+    use crate::compressor::testing::{self, assert_strings, serialized, Collection};
+    use crate::compressor::{OnPair, OnPair16, OnPair16Dictionary, OnPair32, OnPairConfig, OnPairDictionary};
+
+    /// Number of worker threads, which fixes where the chunks start
+    const N_THREADS: usize = 2;
+
+    /// Returns URLs with empty strings scattered among them and around the chunk edges
+    fn strings(n: usize) -> Vec<String> {
+        let mut strings = testing::urls(n, 21);
+        let chunk_len = n.div_ceil(N_THREADS * CHUNKS_PER_THREAD).max(MIN_CHUNK_STRINGS);
+        for (index, string) in strings.iter_mut().enumerate() {
+            if index % 97 == 0 || index % chunk_len <= 1 || index % chunk_len == chunk_len - 1 || index + 1 == n {
+                string.clear();
+            }
+        }
+        strings
+    }
+
+    /// Serializes the parts of a collection like `serialize`
+    fn serialized_parts<T: TokenId>(
+        variant: u8,
+        threshold: u16,
+        dictionary: &[u8],
+        token_boundaries: &[u32],
+        string_boundaries: &[usize],
+        compressed_data: &[T],
+    ) -> Vec<u8> {
+        let header = Header {
+            threshold,
+            dictionary_len: dictionary.len(),
+            n_token_boundaries: token_boundaries.len(),
+            n_string_boundaries: string_boundaries.len(),
+            n_tokens: compressed_data.len(),
+        };
+
+        let mut bytes = Vec::new();
+        header.write(&mut bytes, variant).unwrap();
+        format::write_sections(&mut bytes, dictionary, token_boundaries, string_boundaries.iter().copied(), compressed_data).unwrap();
+        bytes
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn parallel_encoding_matches_sequential() {
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(N_THREADS).build().unwrap();
+        let (sample, sample_end_positions) = flatten_strings(&testing::urls(2000, 20));
+        let dictionary = OnPairDictionary::train(&sample, &sample_end_positions, 3);
+        let dictionary16 = OnPair16Dictionary::train(&sample, &sample_end_positions, 3);
+
+        // One chunk, one string past it, whole chunks, and chunks longer than the minimum
+        for n in [MIN_CHUNK_STRINGS, MIN_CHUNK_STRINGS + 1, 3 * MIN_CHUNK_STRINGS, 40_000] {
+            let strings = strings(n);
+            let (data, end_positions) = flatten_strings(&strings);
+
+            let mut onpair = OnPair::with_dictionary(dictionary.clone());
+            let mut onpair16 = OnPair16::with_dictionary(dictionary16.clone());
+            pool.install(|| {
+                onpair.extend_bytes(&data, &end_positions);
+                onpair16.extend_bytes(&data, &end_positions);
+            });
+            let mut parallel = Vec::new();
+            onpair.serialize(&mut parallel).unwrap();
+            let mut parallel16 = Vec::new();
+            onpair16.serialize(&mut parallel16).unwrap();
+
+            let (mut token_ids, mut string_boundaries) = (Vec::new(), Vec::new());
+            dictionary.encode(&data, &end_positions, &mut token_ids, &mut string_boundaries);
+            let sequential = serialized_parts(
+                format::VARIANT_ONPAIR,
+                dictionary.threshold(),
+                dictionary.token_data(),
+                &dictionary.token_boundaries,
+                &string_boundaries,
+                &token_ids,
+            );
+            assert!(parallel == sequential, "OnPair with {} strings", n);
+
+            let (mut token_ids, mut string_boundaries) = (Vec::new(), Vec::new());
+            dictionary16.encode(&data, &end_positions, &mut token_ids, &mut string_boundaries);
+            let sequential16 = serialized_parts(
+                format::VARIANT_ONPAIR16,
+                dictionary16.threshold(),
+                dictionary16.token_data(),
+                &dictionary16.token_boundaries,
+                &string_boundaries,
+                &token_ids,
+            );
+            assert!(parallel16 == sequential16, "OnPair16 with {} strings", n);
+        }
+    }
+
+    /// Compresses `strings` with parallel training on a pool of `n_threads` threads
+    fn train_on<C: Collection + Send>(strings: &[String], config: OnPairConfig, n_threads: usize) -> C {
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(n_threads).build().unwrap();
+        pool.install(|| testing::compressed(strings, config))
+    }
+
+    fn check_parallel_training<C: Collection + Send>() {
+        let mut strings = testing::urls(3000, 22);
+        strings[100].clear();
+        let config = OnPairConfig::new(3).with_seed(22).with_parallel_training(256);
+
+        // Independent of the number of threads, and reproducible with a seed
+        let compressor = train_on::<C>(&strings, config, 1);
+        let bytes = serialized(&compressor);
+        for n_threads in [1, 2, 3, 8] {
+            assert!(serialized(&train_on::<C>(&strings, config, n_threads)) == bytes, "{} threads", n_threads);
+        }
+        assert_strings(&compressor, &strings);
+        let report = compressor.training_report().unwrap();
+        assert_eq!(report.trained_strings, strings.len());
+        assert!(!report.dictionary_full);
+        assert!(compressor.num_tokens() > 1000);
+
+        // The token limit holds even when a whole batch of pairs reaches the threshold
+        for max_tokens in [256, 300, 1000] {
+            let compressor = train_on::<C>(&strings, config.with_max_tokens(max_tokens), 4);
+            assert_eq!(compressor.num_tokens(), max_tokens);
+            assert_strings(&compressor, &strings);
+        }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn parallel_training_is_deterministic() {
+        check_parallel_training::<OnPair>();
+        check_parallel_training::<OnPair16>();
+        check_parallel_training::<OnPair32>();
+    }
+}
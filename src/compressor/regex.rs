@@ -0,0 +1,371 @@
+//! Regular expression search over token IDs (requires the `regex` feature)
+//!
+//! The regular expression is compiled into a dense byte-level DFA with
+//! `regex-automata`. As for substring search, the state reached from every DFA
+//! state after reading every dictionary token is precomputed, so each token ID of
+//! a string advances the DFA with a single lookup. DFAs with too many states to
+//! tabulate against the dictionary run over the bytes of each token instead.
+//!
+//! Some expressions, such as `[01]*1[01]{20}`, determinize into millions of states.
+//! The dense DFA is built under a size limit, and expressions exceeding it run on a
+//! lazy DFA, whose states are determinized as the strings reach them and kept in a
+//! bounded cache.
+
+use crate::compressor::search::{token, MATCH_FLAG, MAX_TOKEN_TRANSITIONS};
+use crate::compressor::token::TokenId;
+use crate::error::OnPairError;
+use regex_automata::dfa::{dense, Automaton};
+use regex_automata::hybrid;
+use regex_automata::util::primitives::StateID;
+use regex_automata::util::start;
+use rustc_hash::FxHashMap;
+
+/// Maximum size in bytes of the dense DFA, and of the memory used to determinize it
+const MAX_DFA_SIZE: usize = 16 << 20;
+
+/// DFA of a regular expression
+pub(crate) enum RegexAutomaton {
+    /// Dense DFA, with transitions tabulated per token when they fit
+    Dense {
+        dfa: dense::DFA<Vec<u32>>,
+        start: StateID,
+        table: Option<TokenTable>,
+    },
+    /// Lazy DFA, for expressions whose dense DFA exceeds `MAX_DFA_SIZE`
+    Lazy {
+        dfa: hybrid::dfa::DFA,
+        cache: hybrid::dfa::Cache,
+    },
+}
+
+/// DFA transitions over the dictionary tokens, for the states reachable from the start state
+pub(crate) struct TokenTable {
+    n_tokens: usize,
+    transitions: Vec<u32>,  // State index * n_tokens + token ID -> state index | MATCH_FLAG
+    eoi_match: Vec<bool>,   // Whether ending the string in each state is a match
+    dead: Vec<bool>,        // Whether each state can no longer lead to a match
+}
+
+impl RegexAutomaton {
+    /// Compiles `pattern` and tabulates its transitions over the dictionary tokens
+    ///
+    /// A string matches if the expression matches anywhere in it.
+    pub(crate) fn new(pattern: &str, dictionary: &[u8], token_boundaries: &[u32]) -> Result<Self, OnPairError> {
+        Self::with_limits(pattern, dictionary, token_boundaries, MAX_DFA_SIZE, MAX_TOKEN_TRANSITIONS)
+    }
+
+    /// Compiles `pattern` into a dense DFA of at most `max_dfa_size` bytes, tabulating its
+    /// transitions only if there are at most `max_transitions`
+    fn with_limits(
+        pattern: &str,
+        dictionary: &[u8],
+        token_boundaries: &[u32],
+        max_dfa_size: usize,
+        max_transitions: usize,
+    ) -> Result<Self, OnPairError> {
+        let config = dense::Config::new()
+            .dfa_size_limit(Some(max_dfa_size))
+            .determinize_size_limit(Some(max_dfa_size));
+        let dfa = match dense::Builder::new().configure(config).build(pattern) {
+            Ok(dfa) => dfa,
+            Err(error) if error.is_size_limit_exceeded() => return Self::lazy(pattern),
+            Err(error) => return Err(OnPairError::InvalidRegex(error.to_string())),
+        };
+        let start = dfa
+            .start_state(&start::Config::new())
+            .map_err(|error| OnPairError::InvalidRegex(error.to_string()))?;
+        let table = TokenTable::new(&dfa, start, dictionary, token_boundaries, max_transitions);
+
+        Ok(RegexAutomaton::Dense { dfa, start, table })
+    }
+
+    /// Compiles `pattern` into a lazy DFA
+    fn lazy(pattern: &str) -> Result<Self, OnPairError> {
+        let dfa = hybrid::dfa::DFA::new(pattern).map_err(|error| OnPairError::InvalidRegex(error.to_string()))?;
+        let mut cache = dfa.create_cache();
+        dfa.start_state(&mut cache, &start::Config::new())
+            .map_err(|error| OnPairError::InvalidRegex(error.to_string()))?;
+
+        Ok(RegexAutomaton::Lazy { dfa, cache })
+    }
+
+    /// Returns `true` if the expression matches the string made of `token_ids`
+    #[inline]
+    pub(crate) fn is_match<T: TokenId>(&mut self, token_ids: &[T], dictionary: &[u8], token_boundaries: &[u32]) -> bool {
+        match self {
+            RegexAutomaton::Dense { table: Some(table), .. } => table.is_match(token_ids),
+            RegexAutomaton::Dense { dfa, start, table: None } => {
+                is_match_bytes(dfa, *start, token_ids, dictionary, token_boundaries)
+            }
+            RegexAutomaton::Lazy { dfa, cache } => is_match_lazy(dfa, cache, token_ids, dictionary, token_boundaries),
+        }
+    }
+}
+
+/// Runs the dense DFA over the bytes of each token, for DFAs too large to tabulate
+fn is_match_bytes<T: TokenId>(
+    dfa: &dense::DFA<Vec<u32>>,
+    start: StateID,
+    token_ids: &[T],
+    dictionary: &[u8],
+    token_boundaries: &[u32],
+) -> bool {
+    let mut state = start;
+    for &token_id in token_ids {
+        let (next, matched) = walk(dfa, state, token(dictionary, token_boundaries, token_id));
+        if matched {
+            return true;
+        }
+        if dfa.is_dead_state(next) {
+            return false;
+        }
+        state = next;
+    }
+    dfa.is_match_state(dfa.next_eoi_state(state))
+}
+
+/// Runs the lazy DFA over the bytes of each token
+///
+/// Clearing the cache invalidates the states it held, so the start state is looked up again
+/// for every string.
+fn is_match_lazy<T: TokenId>(
+    dfa: &hybrid::dfa::DFA,
+    cache: &mut hybrid::dfa::Cache,
+    token_ids: &[T],
+    dictionary: &[u8],
+    token_boundaries: &[u32],
+) -> bool {
+    // The cache is cleared whenever it fills up, which never fails without a minimum clear count
+    let next_state = |cache: &mut hybrid::dfa::Cache, state, byte| {
+        dfa.next_state(cache, state, byte).expect("lazy DFA cache is never exhausted")
+    };
+
+    let mut state = dfa.start_state(cache, &start::Config::new()).expect("start state was computed on construction");
+    for &token_id in token_ids {
+        for &byte in token(dictionary, token_boundaries, token_id) {
+            state = next_state(cache, state, byte);
+            if state.is_match() {
+                return true;
+            }
+            if state.is_dead() {
+                return false;
+            }
+        }
+    }
+    dfa.next_eoi_state(cache, state).expect("lazy DFA cache is never exhausted").is_match()
+}
+
+impl TokenTable {
+    /// Returns `true` if the expression matches the string made of `token_ids`
+    #[inline]
+    fn is_match<T: TokenId>(&self, token_ids: &[T]) -> bool {
+        let mut state = 0;
+        for &token_id in token_ids {
+            let next = self.transitions[state * self.n_tokens + token_id.index()];
+            if next & MATCH_FLAG != 0 {
+                return true;
+            }
+            state = next as usize;
+            if self.dead[state] {
+                return false;
+            }
+        }
+        self.eoi_match[state]
+    }
+
+    /// Tabulates the transitions, or returns `None` if there would be more than
+    /// `max_transitions` of them
+    fn new(
+        dfa: &dense::DFA<Vec<u32>>,
+        start: StateID,
+        dictionary: &[u8],
+        token_boundaries: &[u32],
+        max_transitions: usize,
+    ) -> Option<Self> {
+        let n_tokens = token_boundaries.len().saturating_sub(1);
+
+        // Number the states reachable from the start state in breadth-first order
+        let mut states = vec![start];
+        let mut indices = FxHashMap::default();
+        indices.insert(start, 0u32);
+        let mut next = 0;
+        while next < states.len() {
+            if states.len() * n_tokens > max_transitions {
+                return None;
+            }
+            let state = states[next];
+            for byte in 0..=u8::MAX {
+                let target = dfa.next_state(state, byte);
+                indices.entry(target).or_insert_with(|| {
+                    states.push(target);
+                    states.len() as u32 - 1
+                });
+            }
+            next += 1;
+        }
+
+        let mut transitions = Vec::with_capacity(states.len() * n_tokens);
+        for &state in &states {
+            for id in 0..n_tokens {
+                let bytes = &dictionary[token_boundaries[id] as usize..token_boundaries[id + 1] as usize];
+                let (target, matched) = walk(dfa, state, bytes);
+                transitions.push(indices[&target] | if matched { MATCH_FLAG } else { 0 });
+            }
+        }
+
+        Some(Self {
+            n_tokens,
+            transitions,
+            eoi_match: states.iter().map(|&state| dfa.is_match_state(dfa.next_eoi_state(state))).collect(),
+            dead: states.iter().map(|&state| dfa.is_dead_state(state)).collect(),
+        })
+    }
+}
+
+/// Runs the DFA over `bytes` from `state`, returning the state reached and whether a match
+/// was found along the way
+///
+/// Matches are reported by the DFA one byte late, so a match ending on the last byte is
+/// only seen on the next token or at the end of the string.
+#[inline]
+fn walk(dfa: &dense::DFA<Vec<u32>>, mut state: StateID, bytes: &[u8]) -> (StateID, bool) {
+    let mut matched = false;
+    for &byte in bytes {
+        state = dfa.next_state(state, byte);
+        matched |= dfa.is_match_state(state);
+    }
+    (state, matched)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compressor::onpair::{flatten_bytes, flatten_strings};
+    use crate::compressor::testing;
+    use crate::compressor::{OnPair, OnPair16, OnPairConfig, OnPairDictionary};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use regex::bytes::Regex;
+
+    const PATTERNS: [&str; 13] = [
+        "example",
+        r"^https://api\.",
+        r"page=1\d$",
+        r"(?-u)users/\d{3}\?",
+        "(orders|items)/9",
+        r"\.org/",
+        "thumbnails/[0-9]+",
+        "^$",
+        "",
+        "a*",
+        "s$",
+        "zzz",
+        "(?i)EXAMPLE",
+    ];
+
+    fn parse(dictionary: &OnPairDictionary, string: &[u8]) -> Vec<u16> {
+        let mut token_ids = Vec::new();
+        dictionary.encode(string, &[0, string.len()], &mut token_ids, &mut Vec::new());
+        token_ids
+    }
+
+    /// Checks the automaton of `pattern` against `regex` on every string, with and without the
+    /// table, and on the lazy DFA
+    fn check(dictionary: &OnPairDictionary, strings: &[Vec<u8>], pattern: &str) {
+        let regex = Regex::new(pattern).unwrap();
+        let (bytes, token_boundaries) = (&dictionary.bytes, &dictionary.token_boundaries);
+
+        let mut tabulated = RegexAutomaton::new(pattern, bytes, token_boundaries).unwrap();
+        assert!(matches!(tabulated, RegexAutomaton::Dense { table: Some(_), .. }), "{:?}", pattern);
+        let mut fallback = RegexAutomaton::with_limits(pattern, bytes, token_boundaries, MAX_DFA_SIZE, 0).unwrap();
+        assert!(matches!(fallback, RegexAutomaton::Dense { table: None, .. }));
+        let mut lazy = RegexAutomaton::with_limits(pattern, bytes, token_boundaries, 0, MAX_TOKEN_TRANSITIONS).unwrap();
+        assert!(matches!(lazy, RegexAutomaton::Lazy { .. }));
+
+        for string in strings {
+            let token_ids = parse(dictionary, string);
+            let expected = regex.is_match(string);
+            assert_eq!(tabulated.is_match(&token_ids, bytes, token_boundaries), expected, "{:?} on {:?}", pattern, string);
+            assert_eq!(fallback.is_match(&token_ids, bytes, token_boundaries), expected, "{:?} on {:?}", pattern, string);
+            assert_eq!(lazy.is_match(&token_ids, bytes, token_boundaries), expected, "{:?} on {:?}", pattern, string);
+        }
+    }
+
+    #[test]
+    fn matches_like_regex() {
+        let mut strings = testing::urls(300, 11).into_iter().map(String::into_bytes).collect::<Vec<_>>();
+        strings.push(Vec::new());
+        let (data, end_positions) = flatten_bytes(&strings);
+        let dictionary = OnPairDictionary::train(&data, &end_positions, 3);
+
+        for pattern in PATTERNS {
+            check(&dictionary, &strings, pattern);
+        }
+    }
+
+    #[test]
+    fn match_ending_on_last_byte_of_token() {
+        let strings = testing::urls(300, 12);
+        let (data, end_positions) = flatten_strings(&strings);
+        let dictionary = OnPairDictionary::train(&data, &end_positions, 3);
+
+        // The DFA reports a match one byte late: after the token, at the next token or at the end
+        let (id, token) = dictionary.tokens().max_by_key(|(_, bytes)| bytes.len()).unwrap();
+        assert!(token.len() > 1);
+        let followed = [token, b"\x01"].concat();
+        assert_eq!(parse(&dictionary, &followed)[0], id);
+
+        let pattern = regex::escape(std::str::from_utf8(token).unwrap());
+        let strings = vec![token.to_vec(), followed, [b"\x01", token].concat(), token[..token.len() - 1].to_vec()];
+        check(&dictionary, &strings, &pattern);
+        check(&dictionary, &strings, &format!("{}$", pattern));
+        check(&dictionary, &strings, &format!("^{}\x01", pattern));
+    }
+
+    #[test]
+    fn collections_match_like_regex() {
+        let mut strings = testing::urls(300, 13);
+        strings.push(String::new());
+
+        let mut onpair = OnPair::with_config(OnPairConfig::new(3).with_seed(13));
+        onpair.compress_strings(&strings);
+        let mut onpair16 = OnPair16::with_config(OnPairConfig::new(3).with_seed(13));
+        onpair16.compress_strings(&strings);
+
+        for pattern in PATTERNS {
+            let regex = Regex::new(pattern).unwrap();
+            let expected = (0..strings.len()).filter(|&index| regex.is_match(strings[index].as_bytes())).collect::<Vec<_>>();
+            assert_eq!(onpair.regex_matches(pattern).unwrap().collect::<Vec<_>>(), expected, "{:?}", pattern);
+            assert_eq!(onpair16.regex_matches(pattern).unwrap().collect::<Vec<_>>(), expected, "{:?}", pattern);
+        }
+
+        assert!(matches!(onpair.regex_matches("(").err(), Some(OnPairError::InvalidRegex(_))));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn large_dfa_runs_lazily() {
+        // The dense DFA needs a state for each of the 2^21 possible last 21 bytes
+        let pattern = "[01]*1[01]{20}";
+        let mut rng = StdRng::seed_from_u64(15);
+        let mut strings = (0..300)
+            .map(|_| {
+                let len = rng.gen_range(0..48);
+                (0..len).map(|_| if rng.gen_bool(0.8) { b'0' } else { b'1' }).collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        strings.extend([vec![b'1'; 21], [&b"1"[..], &[b'0'; 20]].concat(), vec![b'0'; 40], Vec::new()]);
+        let (data, end_positions) = flatten_bytes(&strings);
+        let dictionary = OnPairDictionary::train(&data, &end_positions, 3);
+        let (bytes, token_boundaries) = (&dictionary.bytes, &dictionary.token_boundaries);
+
+        let mut automaton = RegexAutomaton::new(pattern, bytes, token_boundaries).unwrap();
+        assert!(matches!(automaton, RegexAutomaton::Lazy { .. }));
+
+        let regex = Regex::new(pattern).unwrap();
+        for string in &strings {
+            let token_ids = parse(&dictionary, string);
+            assert_eq!(automaton.is_match(&token_ids, bytes, token_boundaries), regex.is_match(string), "{:?}", string);
+        }
+    }
+}
@@ -0,0 +1,279 @@
+//! Training sample selection
+//!
+//! The dictionary usually fills up long before a large input is exhausted, so
+//! training can be restricted to a sample of the strings while every string is
+//! still parsed and compressed with the resulting dictionary.
+
+/// Amount of input used to train the dictionary
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Sample {
+    /// Every string
+    #[default]
+    All,
+    /// The given fraction of the strings, in `(0, 1]` (rounded up)
+    Fraction(f64),
+    /// At most the given number of strings
+    Count(usize),
+    /// Strings until at least the given number of bytes is selected
+    Bytes(usize),
+}
+
+/// How the sampled strings are spread over the input
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SampleStrategy {
+    /// The first strings of the training order (a uniform random sample unless
+    /// shuffling is disabled, in which case it is a prefix of the input)
+    #[default]
+    Random,
+    /// One string from each of equally sized runs of consecutive strings, so that
+    /// every region of the input is represented
+    ///
+    /// Within each run the string is picked at random, or is the first of the run
+    /// when shuffling is disabled.
+    Stratified,
+}
+
+/// Summary of the strings visited and the memory used while training a dictionary
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TrainingReport {
+    /// Number of strings selected for training
+    pub sampled_strings: usize,
+    /// Total length in bytes of the selected strings
+    pub sampled_bytes: usize,
+    /// Number of strings actually parsed before training stopped
+    pub trained_strings: usize,
+    /// Total length in bytes of the strings actually parsed
+    pub trained_bytes: usize,
+    /// Whether training stopped early because the dictionary reached its maximum size
+    pub dictionary_full: bool,
+    /// Estimated peak heap memory (in bytes) allocated by training: the training order,
+    /// the pair frequencies, the matchers and the token storage
+    pub peak_memory: usize,
+}
+
+impl Sample {
+    /// Returns whether the sample size is valid
+    pub(crate) fn is_valid(self) -> bool {
+        match self {
+            Sample::All => true,
+            Sample::Fraction(fraction) => fraction > 0.0 && fraction <= 1.0,
+            Sample::Count(count) => count > 0,
+            Sample::Bytes(budget) => budget > 0,
+        }
+    }
+}
+
+/// Restricts a training order over all strings to the configured sample
+///
+/// The relative order of the selected strings is preserved, so the sample is visited in
+/// training order.
+pub(crate) fn select(mut order: Vec<usize>, end_positions: &[usize], sample: Sample, strategy: SampleStrategy) -> Vec<usize> {
+    let n_strings = order.len();
+    let string_len = |index: usize| end_positions[index + 1] - end_positions[index];
+
+    // Number of strings to select; for a byte budget, an estimate based on the average length
+    let count = match sample {
+        Sample::All => n_strings,
+        Sample::Fraction(fraction) => (n_strings as f64 * fraction).ceil() as usize,
+        Sample::Count(count) => count,
+        Sample::Bytes(budget) => {
+            let total_bytes = end_positions.last().map_or(0, |&last| last - end_positions[0]);
+            match total_bytes {
+                0 => n_strings,
+                _ => (n_strings as f64 * budget as f64 / total_bytes as f64).ceil() as usize,
+            }
+        }
+    }
+    .min(n_strings);
+
+    if strategy == SampleStrategy::Stratified && count < n_strings {
+        order = stratify(&order, count);
+    }
+
+    match sample {
+        Sample::Bytes(budget) => {
+            let mut selected_bytes = 0;
+            let len = order
+                .iter()
+                .take_while(|&&index| {
+                    let below_budget = selected_bytes < budget;
+                    selected_bytes += string_len(index);
+                    below_budget
+                })
+                .count();
+            order.truncate(len);
+        }
+        _ => order.truncate(count),
+    }
+
+    order
+}
+
+/// Picks, for each of `count` runs of consecutive strings, the string of the run that
+/// comes first in `order`
+fn stratify(order: &[usize], count: usize) -> Vec<usize> {
+    let n_strings = order.len() as u128;
+    let mut picked = vec![false; count];
+    let mut selected = Vec::with_capacity(count);
+
+    for &index in order {
+        let stratum = (index as u128 * count as u128 / n_strings) as usize;
+        if !picked[stratum] {
+            picked[stratum] = true;
+            selected.push(index);
+            if selected.len() == count {
+                break;
+            }
+        }
+    }
+
+    selected
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compressor::onpair::flatten_strings;
+    use crate::compressor::testing::{self, assert_strings, compressed, Collection};
+    use crate::compressor::{OnPair, OnPair16, OnPairConfig, Shuffle};
+
+    /// End positions of `n` strings of `len` bytes each
+    fn uniform(n: usize, len: usize) -> Vec<usize> {
+        (0..=n).map(|index| index * len).collect()
+    }
+
+    fn selected(n: usize, len: usize, sample: Sample, strategy: SampleStrategy) -> Vec<usize> {
+        select(Shuffle::Seeded(12).order(n), &uniform(n, len), sample, strategy)
+    }
+
+    #[test]
+    fn sample_sizes() {
+        for strategy in [SampleStrategy::Random, SampleStrategy::Stratified] {
+            assert_eq!(selected(100, 10, Sample::All, strategy).len(), 100);
+
+            assert_eq!(selected(100, 10, Sample::Fraction(0.25), strategy).len(), 25);
+            assert_eq!(selected(10, 10, Sample::Fraction(0.25), strategy).len(), 3);
+            assert_eq!(selected(100, 10, Sample::Fraction(1e-9), strategy).len(), 1);
+            assert_eq!(selected(100, 10, Sample::Fraction(1.0), strategy).len(), 100);
+
+            assert_eq!(selected(100, 10, Sample::Count(1), strategy).len(), 1);
+            assert_eq!(selected(100, 10, Sample::Count(40), strategy).len(), 40);
+            assert_eq!(selected(100, 10, Sample::Count(1000), strategy).len(), 100);
+
+            // Strings are added until the budget is reached, so the last one may cross it
+            assert_eq!(selected(100, 10, Sample::Bytes(1), strategy).len(), 1);
+            assert_eq!(selected(100, 10, Sample::Bytes(95), strategy).len(), 10);
+            assert_eq!(selected(100, 10, Sample::Bytes(100), strategy).len(), 10);
+            assert_eq!(selected(100, 10, Sample::Bytes(1 << 20), strategy).len(), 100);
+            assert_eq!(selected(100, 0, Sample::Bytes(1), strategy).len(), 100);
+
+            assert!(selected(0, 10, Sample::Fraction(0.5), strategy).is_empty());
+            assert!(selected(0, 10, Sample::Bytes(10), strategy).is_empty());
+        }
+    }
+
+    #[test]
+    fn sample_bytes_with_uneven_lengths() {
+        // Lengths 0, 1, 2, ..., 9 visited in input order
+        let end_positions: Vec<usize> = (0..=10).map(|index| index * (index.max(1) - 1) / 2).collect();
+        let order: Vec<usize> = (0..10).collect();
+        assert_eq!(select(order.clone(), &end_positions, Sample::Bytes(6), SampleStrategy::Random), [0, 1, 2, 3]);
+        assert_eq!(select(order, &end_positions, Sample::Bytes(7), SampleStrategy::Random), [0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn sample_keeps_training_order() {
+        let order = Shuffle::Seeded(12).order(100);
+        assert_eq!(selected(100, 10, Sample::Count(30), SampleStrategy::Random), order[..30]);
+        assert_eq!(select((0..100).collect(), &uniform(100, 10), Sample::Count(30), SampleStrategy::Random), (0..30).collect::<Vec<_>>());
+
+        let stratified = selected(100, 10, Sample::Count(30), SampleStrategy::Stratified);
+        let positions: Vec<usize> = stratified.iter().map(|index| order.iter().position(|other| other == index).unwrap()).collect();
+        assert!(positions.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn stratified_covers_the_whole_input() {
+        for (n, count) in [(1000, 10), (1000, 7), (10, 10), (10, 1), (3, 2)] {
+            let selected = selected(n, 1, Sample::Count(count), SampleStrategy::Stratified);
+            assert_eq!(selected.len(), count);
+            let mut strata: Vec<usize> = selected.iter().map(|&index| index * count / n).collect();
+            strata.sort_unstable();
+            assert_eq!(strata, (0..count).collect::<Vec<_>>(), "{} of {} strings", count, n);
+        }
+
+        // Without shuffling, each stratum contributes its first string
+        let selected = select((0..100).collect(), &uniform(100, 1), Sample::Count(4), SampleStrategy::Stratified);
+        assert_eq!(selected, [0, 25, 50, 75]);
+    }
+
+    #[test]
+    fn sample_validity() {
+        assert!(Sample::All.is_valid());
+        assert!(Sample::Fraction(1.0).is_valid());
+        assert!(Sample::Fraction(f64::MIN_POSITIVE).is_valid());
+        assert!(Sample::Count(1).is_valid());
+        assert!(Sample::Bytes(1).is_valid());
+
+        assert!(!Sample::Fraction(0.0).is_valid());
+        assert!(!Sample::Fraction(-0.5).is_valid());
+        assert!(!Sample::Fraction(1.5).is_valid());
+        assert!(!Sample::Fraction(f64::NAN).is_valid());
+        assert!(!Sample::Count(0).is_valid());
+        assert!(!Sample::Bytes(0).is_valid());
+    }
+
+    fn check_training_report<C: Collection>(strings: &[String]) {
+        let lengths: Vec<usize> = strings.iter().map(String::len).collect();
+        let total_bytes: usize = lengths.iter().sum();
+        let base = OnPairConfig::new(3).with_seed(12);
+
+        let configs = [
+            (base, strings.len()),
+            (base.with_sample_fraction(0.1), 50),
+            (base.with_sample_count(1), 1),
+            (base.with_sample_count(10_000), strings.len()),
+            (base.with_sample_bytes(2000), 0),
+            (base.with_sample_count(100).with_sample_strategy(SampleStrategy::Stratified), 100),
+            (base.with_shuffle(Shuffle::Disabled).with_sample_count(100), 100),
+            (base.with_max_tokens(300), strings.len()),
+        ];
+
+        for (config, expected_strings) in configs {
+            let collection = compressed::<C>(strings, config);
+            let report = collection.training_report().unwrap();
+
+            let order = config.training_order(&flatten_strings(strings).1);
+            assert_eq!(report.sampled_strings, order.len());
+            assert_eq!(report.sampled_bytes, order.iter().map(|&index| lengths[index]).sum::<usize>());
+            if let Sample::Bytes(budget) = config.sample() {
+                assert!(report.sampled_bytes >= budget);
+                assert!(report.sampled_bytes - lengths[*order.last().unwrap()] < budget);
+            } else {
+                assert_eq!(report.sampled_strings, expected_strings);
+            }
+
+            assert!(report.trained_strings <= report.sampled_strings);
+            assert!(report.trained_bytes <= report.sampled_bytes);
+            assert!(report.sampled_bytes <= total_bytes);
+            assert!(report.peak_memory > 0);
+            assert_eq!(report.dictionary_full, config.max_tokens().is_some());
+            if report.dictionary_full {
+                assert_eq!(collection.num_tokens(), config.max_tokens().unwrap_or(1 << 16));
+            } else {
+                assert_eq!((report.trained_strings, report.trained_bytes), (report.sampled_strings, report.sampled_bytes));
+            }
+
+            assert_strings(&collection, strings);
+        }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn training_report_matches_sample() {
+        let mut strings = testing::urls(500, 12);
+        strings[7].clear();
+        check_training_report::<OnPair>(&strings);
+        check_training_report::<OnPair16>(&strings);
+    }
+}
@@ -0,0 +1,432 @@
+//! Predicates evaluated on token IDs, without decompressing the strings
+//!
+//! A string is a sequence of token IDs, and the bytes of each token are a slice
+//! of the dictionary. The predicates below walk the token IDs of a string and
+//! compare the bytes of whole tokens in place, stopping at the first mismatch.
+
+use crate::compressor::token::TokenId;
+
+/// Returns the bytes of token `token_id`
+#[inline(always)]
+pub(crate) fn token<'a, T: TokenId>(dictionary: &'a [u8], token_boundaries: &[u32], token_id: T) -> &'a [u8] {
+    let id = token_id.index();
+    &dictionary[token_boundaries[id] as usize..token_boundaries[id + 1] as usize]
+}
+
+/// Returns `true` if the string made of `token_ids` starts with `prefix`
+///
+/// Tokens that lie inside the prefix are compared whole, and only the token crossing the end
+/// of the prefix is compared partially.
+#[inline]
+pub(crate) fn starts_with<T: TokenId>(token_ids: &[T], dictionary: &[u8], token_boundaries: &[u32], prefix: &[u8]) -> bool {
+    let mut rest = prefix;
+    for &token_id in token_ids {
+        if rest.is_empty() {
+            return true;
+        }
+
+        let bytes = token(dictionary, token_boundaries, token_id);
+        if bytes.len() >= rest.len() {
+            return bytes.starts_with(rest);
+        }
+        if !rest.starts_with(bytes) {
+            return false;
+        }
+        rest = &rest[bytes.len()..];
+    }
+
+    rest.is_empty()
+}
+
+/// Returns `true` if the string made of `token_ids` ends with `suffix`
+///
+/// Tokens are visited from the last one. Tokens that lie inside the suffix are compared whole,
+/// and only the token crossing the start of the suffix is compared partially.
+#[inline]
+pub(crate) fn ends_with<T: TokenId>(token_ids: &[T], dictionary: &[u8], token_boundaries: &[u32], suffix: &[u8]) -> bool {
+    let mut rest = suffix;
+    for &token_id in token_ids.iter().rev() {
+        if rest.is_empty() {
+            return true;
+        }
+
+        let bytes = token(dictionary, token_boundaries, token_id);
+        if bytes.len() >= rest.len() {
+            return bytes.ends_with(rest);
+        }
+        if !rest.ends_with(bytes) {
+            return false;
+        }
+        rest = &rest[..rest.len() - bytes.len()];
+    }
+
+    rest.is_empty()
+}
+
+/// Maximum number of tabulated token transitions (states times tokens), beyond which the
+/// automaton walks the bytes of each token instead
+pub(crate) const MAX_TOKEN_TRANSITIONS: usize = 1 << 22;
+
+/// Flag set on a tabulated token transition when a match ends inside the token
+pub(crate) const MATCH_FLAG: u32 = 1 << 31;
+
+/// Aho–Corasick automaton over a set of patterns, with transitions tabulated per token
+///
+/// The byte automaton is a complete DFA: every state has a transition for every byte, and
+/// each state knows the patterns that end there. On top of it, the state reached from every
+/// state after reading the bytes of every token is precomputed, so each token ID of a string
+/// advances the automaton with a single lookup. Patterns ending inside a token are recovered
+/// by walking the bytes of that token only.
+pub(crate) struct SubstringAutomaton {
+    transitions: Vec<u32>,                  // Byte transitions: state * 256 + byte -> state
+    outputs: Vec<Vec<u32>>,                 // Patterns ending at each state
+    token_transitions: Option<Vec<u32>>,    // Token transitions: state * n_tokens + token ID -> state | MATCH_FLAG
+    n_tokens: usize,
+}
+
+impl SubstringAutomaton {
+    /// Builds the automaton of `patterns` and tabulates its transitions over the dictionary tokens
+    pub(crate) fn new<P: AsRef<[u8]>>(patterns: &[P], dictionary: &[u8], token_boundaries: &[u32]) -> Self {
+        Self::with_max_transitions(patterns, dictionary, token_boundaries, MAX_TOKEN_TRANSITIONS)
+    }
+
+    /// Builds the automaton of `patterns`, tabulating its token transitions only if there are
+    /// at most `max_transitions`
+    fn with_max_transitions<P: AsRef<[u8]>>(
+        patterns: &[P],
+        dictionary: &[u8],
+        token_boundaries: &[u32],
+        max_transitions: usize,
+    ) -> Self {
+        // Trie of the patterns, with missing transitions marked by `u32::MAX`
+        let mut transitions = vec![u32::MAX; 256];
+        let mut outputs = vec![Vec::new()];
+        for (pattern_id, pattern) in patterns.iter().enumerate() {
+            let mut state = 0;
+            for &byte in pattern.as_ref() {
+                let next = transitions[state * 256 + byte as usize];
+                state = if next == u32::MAX {
+                    let new_state = outputs.len();
+                    transitions[state * 256 + byte as usize] = new_state as u32;
+                    transitions.resize(transitions.len() + 256, u32::MAX);
+                    outputs.push(Vec::new());
+                    new_state
+                } else {
+                    next as usize
+                };
+            }
+            outputs[state].push(pattern_id as u32);
+        }
+        let n_states = outputs.len();
+        assert!(n_states < MATCH_FLAG as usize, "Too many pattern bytes");
+
+        // Breadth-first completion: a missing transition follows the one of the failure state,
+        // and every state inherits the patterns of its failure state
+        let mut failure = vec![0u32; n_states];
+        let mut queue = std::collections::VecDeque::new();
+        for transition in &mut transitions[..256] {
+            match *transition {
+                u32::MAX => *transition = 0,
+                next => queue.push_back(next),
+            }
+        }
+        while let Some(state) = queue.pop_front() {
+            let state = state as usize;
+            let inherited = outputs[failure[state] as usize].clone();
+            outputs[state].extend(inherited);
+
+            for byte in 0..256 {
+                let fallback = transitions[failure[state] as usize * 256 + byte];
+                match transitions[state * 256 + byte] {
+                    u32::MAX => transitions[state * 256 + byte] = fallback,
+                    next => {
+                        failure[next as usize] = fallback;
+                        queue.push_back(next);
+                    }
+                }
+            }
+        }
+
+        let mut automaton = Self { transitions, outputs, token_transitions: None, n_tokens: 0 };
+
+        let n_tokens = token_boundaries.len().saturating_sub(1);
+        if n_states * n_tokens <= max_transitions {
+            let mut token_transitions = Vec::with_capacity(n_states * n_tokens);
+            for state in 0..n_states as u32 {
+                for id in 0..n_tokens {
+                    let bytes = &dictionary[token_boundaries[id] as usize..token_boundaries[id + 1] as usize];
+                    token_transitions.push(automaton.walk(state, bytes));
+                }
+            }
+            automaton.token_transitions = Some(token_transitions);
+            automaton.n_tokens = n_tokens;
+        }
+
+        automaton
+    }
+
+    /// Returns the state reached from `state` after reading `byte`
+    #[inline(always)]
+    fn step(&self, state: u32, byte: u8) -> u32 {
+        self.transitions[state as usize * 256 + byte as usize]
+    }
+
+    /// Returns `true` if a pattern ends at `state`
+    #[inline(always)]
+    fn is_accepting(&self, state: u32) -> bool {
+        !self.outputs[state as usize].is_empty()
+    }
+
+    /// Returns the state reached from `state` after reading `bytes`, flagged if a pattern ends inside them
+    #[inline]
+    fn walk(&self, state: u32, bytes: &[u8]) -> u32 {
+        let mut matched = false;
+        let mut state = state;
+        for &byte in bytes {
+            state = self.step(state, byte);
+            matched |= self.is_accepting(state);
+        }
+        state | if matched { MATCH_FLAG } else { 0 }
+    }
+
+    /// Returns the state reached from `state` after reading a token, flagged if a pattern ends inside it
+    #[inline(always)]
+    fn step_token<T: TokenId>(&self, state: u32, token_id: T, dictionary: &[u8], token_boundaries: &[u32]) -> u32 {
+        match &self.token_transitions {
+            Some(token_transitions) => token_transitions[state as usize * self.n_tokens + token_id.index()],
+            None => self.walk(state, token(dictionary, token_boundaries, token_id)),
+        }
+    }
+
+    /// Returns `true` if the string made of `token_ids` contains at least one of the patterns
+    #[inline]
+    pub(crate) fn matches_any<T: TokenId>(&self, token_ids: &[T], dictionary: &[u8], token_boundaries: &[u32]) -> bool {
+        // An empty pattern is contained in every string
+        if self.is_accepting(0) {
+            return true;
+        }
+
+        let mut state = 0;
+        for &token_id in token_ids {
+            state = self.step_token(state, token_id, dictionary, token_boundaries);
+            if state & MATCH_FLAG != 0 {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Calls `found` with the index of every pattern contained in the string made of `token_ids`
+    ///
+    /// A pattern occurring several times may be reported several times.
+    pub(crate) fn for_each_match<T: TokenId>(
+        &self,
+        token_ids: &[T],
+        dictionary: &[u8],
+        token_boundaries: &[u32],
+        mut found: impl FnMut(usize),
+    ) {
+        self.outputs[0].iter().for_each(|&pattern_id| found(pattern_id as usize));
+
+        let mut state = 0;
+        for &token_id in token_ids {
+            let next = self.step_token(state, token_id, dictionary, token_boundaries);
+            if next & MATCH_FLAG != 0 {
+                // Walk the bytes of the token to find which patterns end inside it
+                let mut byte_state = state;
+                for &byte in token(dictionary, token_boundaries, token_id) {
+                    byte_state = self.step(byte_state, byte);
+                    self.outputs[byte_state as usize].iter().for_each(|&pattern_id| found(pattern_id as usize));
+                }
+            }
+            state = next & !MATCH_FLAG;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compressor::onpair::flatten_strings;
+    use crate::compressor::testing;
+    use crate::compressor::{CompressedStrings, OnPair, OnPair16, OnPairConfig, OnPairDictionary};
+
+    /// URLs sharing long tokens, and the empty string
+    fn strings() -> Vec<String> {
+        let mut strings = testing::urls(300, 14);
+        strings.push(String::new());
+        strings
+    }
+
+    fn compressors(strings: &[String]) -> (OnPair, OnPair16) {
+        let mut onpair = OnPair::with_config(OnPairConfig::new(3).with_seed(14));
+        onpair.compress_strings(strings);
+        let mut onpair16 = OnPair16::with_config(OnPairConfig::new(3).with_seed(14));
+        onpair16.compress_strings(strings);
+        (onpair, onpair16)
+    }
+
+    /// Returns the indices of the decompressed strings satisfying `predicate`
+    fn expected<C: CompressedStrings>(collection: &C, predicate: impl Fn(&[u8]) -> bool) -> Vec<usize> {
+        (0..collection.len()).filter(|&index| predicate(&collection.get(index))).collect()
+    }
+
+    /// Returns every prefix of a few strings, most of which end inside a token, each of
+    /// these strings extended by one byte, and a needle found nowhere
+    fn prefixes(strings: &[String]) -> Vec<Vec<u8>> {
+        let mut needles = vec![b"zzz".to_vec()];
+        for string in strings.iter().step_by(50) {
+            let bytes = string.as_bytes();
+            needles.extend((0..=bytes.len()).map(|len| bytes[..len].to_vec()));
+            needles.push([bytes, b"/"].concat());
+        }
+        needles
+    }
+
+    #[test]
+    fn starts_with_matches_decompressed() {
+        let strings = strings();
+        let (onpair, onpair16) = compressors(&strings);
+
+        for prefix in prefixes(&strings) {
+            let expected_onpair = expected(&onpair, |string| string.starts_with(&prefix));
+            assert_eq!(onpair.starts_with(&prefix).collect::<Vec<_>>(), expected_onpair, "{:?}", prefix);
+            let expected_onpair16 = expected(&onpair16, |string| string.starts_with(&prefix));
+            assert_eq!(onpair16.starts_with(&prefix).collect::<Vec<_>>(), expected_onpair16, "{:?}", prefix);
+        }
+
+        // The empty needle matches every string, including the empty one
+        assert_eq!(onpair.starts_with(b"").count(), strings.len());
+        assert_eq!(OnPair::new(3).starts_with(b"").count(), 0);
+    }
+
+    /// Returns every suffix of a few strings, most of which start inside a token, each of
+    /// these strings preceded by one byte, and a needle found nowhere
+    fn suffixes(strings: &[String]) -> Vec<Vec<u8>> {
+        let mut needles = vec![b"zzz".to_vec()];
+        for string in strings.iter().step_by(50) {
+            let bytes = string.as_bytes();
+            needles.extend((0..=bytes.len()).map(|start| bytes[start..].to_vec()));
+            needles.push([b"/", bytes].concat());
+        }
+        needles
+    }
+
+    #[test]
+    fn ends_with_matches_decompressed() {
+        let strings = strings();
+        let (onpair, onpair16) = compressors(&strings);
+
+        for suffix in suffixes(&strings) {
+            let expected_onpair = expected(&onpair, |string| string.ends_with(&suffix));
+            assert_eq!(onpair.ends_with(&suffix).collect::<Vec<_>>(), expected_onpair, "{:?}", suffix);
+            let expected_onpair16 = expected(&onpair16, |string| string.ends_with(&suffix));
+            assert_eq!(onpair16.ends_with(&suffix).collect::<Vec<_>>(), expected_onpair16, "{:?}", suffix);
+        }
+
+        // The empty needle matches every string, including the empty one
+        assert_eq!(onpair.ends_with(b"").count(), strings.len());
+        assert_eq!(OnPair::new(3).ends_with(b"").count(), 0);
+    }
+
+    /// Returns substrings of up to 12 bytes of a few strings at several offsets, most of which
+    /// start and end inside tokens, each of these strings extended by one byte, the empty
+    /// needle and a needle found nowhere
+    fn substrings(strings: &[String]) -> Vec<Vec<u8>> {
+        let mut needles = vec![Vec::new(), b"zzz".to_vec()];
+        for string in strings.iter().step_by(50) {
+            let bytes = string.as_bytes();
+            for start in (0..bytes.len()).step_by(7) {
+                let end = bytes.len().min(start + 12);
+                needles.extend((start + 1..=end).map(|end| bytes[start..end].to_vec()));
+            }
+            needles.push([bytes, b"/"].concat());
+        }
+        needles
+    }
+
+    /// Sets of patterns that overlap, repeat, are empty or are found nowhere
+    const PATTERN_SETS: [&[&str]; 7] = [
+        &["example", "ample", "example"],
+        &["https://", "s://", "/", "page="],
+        &["users/1", "users/12", "sers"],
+        &["", "zzz"],
+        &["zzz", "qqq"],
+        &["1", "1", "11"],
+        &[],
+    ];
+
+    fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+        needle.is_empty() || haystack.windows(needle.len()).any(|window| window == needle)
+    }
+
+    #[test]
+    fn contains_matches_decompressed() {
+        let strings = strings();
+        let (onpair, onpair16) = compressors(&strings);
+
+        for pattern in substrings(&strings) {
+            let expected_onpair = expected(&onpair, |string| contains(string, &pattern));
+            assert_eq!(onpair.contains(&pattern).collect::<Vec<_>>(), expected_onpair, "{:?}", pattern);
+            let expected_onpair16 = expected(&onpair16, |string| contains(string, &pattern));
+            assert_eq!(onpair16.contains(&pattern).collect::<Vec<_>>(), expected_onpair16, "{:?}", pattern);
+        }
+    }
+
+    #[test]
+    fn contains_any_and_masks_match_decompressed() {
+        let strings = strings();
+        let (onpair, onpair16) = compressors(&strings);
+
+        for patterns in PATTERN_SETS {
+            let any = |string: &[u8]| patterns.iter().any(|pattern| contains(string, pattern.as_bytes()));
+            assert_eq!(onpair.contains_any(patterns).collect::<Vec<_>>(), expected(&onpair, any), "{:?}", patterns);
+            assert_eq!(onpair16.contains_any(patterns).collect::<Vec<_>>(), expected(&onpair16, any), "{:?}", patterns);
+
+            let masks = onpair.contains_masks(patterns);
+            let masks16 = onpair16.contains_masks(patterns);
+            assert_eq!(masks.len(), patterns.len());
+            for (i, pattern) in patterns.iter().enumerate() {
+                let expected = expected(&onpair, |string| contains(string, pattern.as_bytes()));
+                assert_eq!(masks[i].iter_ones().collect::<Vec<_>>(), expected, "{:?}", pattern);
+                assert_eq!(masks16[i].iter_ones().collect::<Vec<_>>(), expected, "{:?}", pattern);
+            }
+        }
+    }
+
+    #[test]
+    fn automaton_without_token_transitions() {
+        let strings = strings();
+        let (data, end_positions) = flatten_strings(&strings);
+        let dictionary = OnPairDictionary::train(&data, &end_positions, 3);
+        let (bytes, token_boundaries) = (&dictionary.bytes, &dictionary.token_boundaries);
+        let token_ids = strings
+            .iter()
+            .map(|string| {
+                let mut token_ids = Vec::new();
+                dictionary.encode(string.as_bytes(), &[0, string.len()], &mut token_ids, &mut Vec::new());
+                token_ids
+            })
+            .collect::<Vec<Vec<u16>>>();
+
+        let singletons = substrings(&strings).into_iter().step_by(5).map(|pattern| vec![pattern]);
+        let sets = PATTERN_SETS.iter().map(|patterns| patterns.iter().map(|pattern| pattern.as_bytes().to_vec()).collect());
+        for patterns in singletons.chain(sets).collect::<Vec<Vec<Vec<u8>>>>() {
+            let tabulated = SubstringAutomaton::new(&patterns, bytes, token_boundaries);
+            assert!(tabulated.token_transitions.is_some());
+            let fallback = SubstringAutomaton::with_max_transitions(&patterns, bytes, token_boundaries, 0);
+            assert!(fallback.token_transitions.is_none());
+
+            for (string, token_ids) in strings.iter().zip(&token_ids) {
+                let found = patterns.iter().map(|pattern| contains(string.as_bytes(), pattern)).collect::<Vec<_>>();
+                for automaton in [&tabulated, &fallback] {
+                    assert_eq!(automaton.matches_any(token_ids, bytes, token_boundaries), found.contains(&true));
+
+                    let mut reported = vec![false; patterns.len()];
+                    automaton.for_each_match(token_ids, bytes, token_boundaries, |pattern| reported[pattern] = true);
+                    assert_eq!(reported, found, "{:?} in {:?}", patterns, string);
+                }
+            }
+        }
+    }
+}
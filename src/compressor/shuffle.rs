@@ -0,0 +1,84 @@
+//! Training order for the dictionary population phase
+
+use rand::seq::SliceRandom;
+use rand::{thread_rng, Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+/// Order in which the training phase visits the input strings
+///
+/// Training is sensitive to the order of the strings, so only `Seeded` and
+/// `Disabled` guarantee that identical input always yields identical output.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Shuffle {
+    /// Random permutation drawn from the thread-local generator (non-deterministic)
+    #[default]
+    Random,
+    /// Random permutation drawn from a ChaCha8 generator seeded with the given value
+    ///
+    /// ChaCha8 is a portable generator with a fixed output stream, so the permutation does not
+    /// depend on the platform or on the version of `rand`.
+    Seeded(u64),
+    /// Strings are visited in input order
+    Disabled,
+}
+
+impl Shuffle {
+    /// Returns the order in which `n_strings` strings are visited
+    pub(crate) fn order(self, n_strings: usize) -> Vec<usize> {
+        match self {
+            Shuffle::Random => shuffled_order(n_strings, &mut thread_rng()),
+            Shuffle::Seeded(seed) => shuffled_order(n_strings, &mut ChaCha8Rng::seed_from_u64(seed)),
+            Shuffle::Disabled => (0..n_strings).collect(),
+        }
+    }
+}
+
+/// Returns a permutation of `0..n_strings` drawn from the given generator
+pub(crate) fn shuffled_order<R: Rng + ?Sized>(n_strings: usize, rng: &mut R) -> Vec<usize> {
+    let mut shuffled_indices: Vec<usize> = (0..n_strings).collect();
+    shuffled_indices.shuffle(rng);
+    shuffled_indices
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compressor::testing;
+    use crate::{OnPair, OnPair16};
+
+    #[test]
+    fn seeded_order_is_fixed() {
+        // Pinned, so that a dependency bump changing seeded dictionaries fails here
+        assert_eq!(Shuffle::Seeded(42).order(10), [0, 3, 5, 7, 9, 4, 8, 1, 6, 2]);
+        assert_eq!(Shuffle::Seeded(42).order(10), Shuffle::Seeded(42).order(10));
+        assert_ne!(Shuffle::Seeded(42).order(10), Shuffle::Seeded(43).order(10));
+        assert_eq!(Shuffle::Disabled.order(5), [0, 1, 2, 3, 4]);
+        assert!(Shuffle::Random.order(0).is_empty());
+    }
+
+    /// Compresses `strings` twice with the same shuffle and checks that both blobs are identical
+    fn assert_reproducible(strings: &[String], shuffle: Shuffle) {
+        let serialized = || {
+            let mut onpair = OnPair::new(3);
+            onpair.set_shuffle(shuffle);
+            onpair.compress_strings(strings);
+            let mut bytes = Vec::new();
+            onpair.serialize(&mut bytes).unwrap();
+
+            let mut onpair16 = OnPair16::new(3);
+            onpair16.set_shuffle(shuffle);
+            onpair16.compress_strings(strings);
+            let mut bytes16 = Vec::new();
+            onpair16.serialize(&mut bytes16).unwrap();
+            (bytes, bytes16)
+        };
+        assert!(serialized() == serialized(), "{:?}", shuffle);
+    }
+
+    #[test]
+    fn seeded_and_disabled_are_reproducible() {
+        let strings = testing::urls(1000, 17);
+        assert_reproducible(&strings, Shuffle::Seeded(17));
+        assert_reproducible(&strings, Shuffle::Disabled);
+    }
+}
@@ -0,0 +1,103 @@
+//! Memory accounting for compressed collections
+//!
+//! `space_used()` counts the bytes of data a collection stores, which is what the
+//! compression ratio is computed on. A `SizeReport` breaks that figure down by
+//! component and puts it next to the heap memory actually allocated, which also
+//! includes spare capacity, padding and the matcher used for parsing, and next
+//! to the memory that training the dictionary needed.
+
+/// Breakdown of the memory used by a compressed collection
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SizeReport {
+    /// Bytes of encoded token IDs
+    pub payload: usize,
+    /// Bytes of string end positions
+    pub string_boundaries: usize,
+    /// Bytes of token data
+    pub dictionary: usize,
+    /// Bytes of token end positions
+    pub token_boundaries: usize,
+    /// Heap memory (in bytes) allocated by the collection, including spare capacity,
+    /// padding and the matcher if it has been built
+    pub heap_capacity: usize,
+    /// Estimated peak heap memory (in bytes) allocated while training the dictionary, or
+    /// `None` if the dictionary was not trained in this process (e.g. it was deserialized)
+    pub peak_training_memory: Option<usize>,
+}
+
+impl SizeReport {
+    /// Returns the bytes of stored data, which equals `space_used()`
+    pub fn total(&self) -> usize {
+        self.payload + self.string_boundaries + self.dictionary + self.token_boundaries
+    }
+
+    /// Returns the heap memory (in bytes) allocated beyond the stored data
+    pub fn overhead(&self) -> usize {
+        self.heap_capacity.saturating_sub(self.total())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compressor::testing::{self, compressed, serialized, Collection};
+    use crate::compressor::{OnPair, OnPair16, OnPair32, OnPairConfig, OnPairPacked};
+
+    fn check_report<C: Collection>(collection: &C) {
+        let report = collection.size_report();
+        assert_eq!(report.total(), collection.space_used());
+        assert_eq!(report.heap_capacity, collection.heap_size());
+        assert!(collection.heap_size() >= collection.space_used());
+        assert_eq!(report.overhead(), collection.heap_size() - collection.space_used());
+    }
+
+    fn check_size_report<C: Collection>(strings: &[String]) {
+        let mut collection = compressed::<C>(strings, OnPairConfig::new(3).with_seed(19));
+        check_report(&collection);
+        let report = collection.size_report();
+        assert_eq!(report.peak_training_memory, Some(collection.training_report().unwrap().peak_memory));
+        assert!(report.dictionary > 0 && report.token_boundaries > 0);
+        assert_eq!(report.payload > 0, strings.iter().any(|string| !string.is_empty()));
+
+        // Shrinking releases spare capacity only
+        let space_used = collection.space_used();
+        let heap_size = collection.heap_size();
+        collection.shrink_to_fit();
+        check_report(&collection);
+        assert_eq!(collection.space_used(), space_used);
+        assert!(collection.heap_size() <= heap_size);
+
+        let deserialized = C::deserialize(&serialized(&collection)).unwrap();
+        check_report(&deserialized);
+        assert_eq!(deserialized.size_report().peak_training_memory, None);
+        assert_eq!(deserialized.space_used(), space_used);
+        assert_eq!(deserialized.size_report().total(), report.total());
+    }
+
+    #[test]
+    fn size_report_is_consistent() {
+        let strings = testing::urls(300, 19);
+        check_size_report::<OnPair>(&strings);
+        check_size_report::<OnPair16>(&strings);
+        check_size_report::<OnPair32>(&strings);
+        check_size_report::<OnPairPacked>(&strings);
+
+        check_size_report::<OnPair>(&[]);
+        check_size_report::<OnPair16>(&[String::new()]);
+    }
+
+    #[test]
+    fn total_and_overhead() {
+        let report = SizeReport {
+            payload: 10,
+            string_boundaries: 20,
+            dictionary: 30,
+            token_boundaries: 40,
+            heap_capacity: 150,
+            peak_training_memory: None,
+        };
+        assert_eq!(report.total(), 100);
+        assert_eq!(report.overhead(), 50);
+        assert_eq!(SizeReport { heap_capacity: 90, ..report }.overhead(), 0);
+    }
+}
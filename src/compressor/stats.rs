@@ -0,0 +1,165 @@
+//! Compression statistics
+//!
+//! Statistics are computed on demand from the token stream, the string boundaries
+//! and the dictionary, so collecting them costs one pass over the token IDs and
+//! nothing is tracked while compressing.
+
+use crate::compressor::boundaries::StringBoundaries;
+use crate::compressor::config::N_BYTE_TOKENS;
+use crate::compressor::token::TokenId;
+
+/// Summary of a compressed collection and of the dictionary it was parsed with
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CompressionStats {
+    /// Number of strings in the collection
+    pub n_strings: usize,
+    /// Number of tokens in the dictionary
+    pub n_tokens: usize,
+    /// Number of tokens created by training, beyond the single-byte tokens
+    pub n_merged_tokens: usize,
+    /// Average length in bytes of the dictionary tokens
+    pub average_token_length: f64,
+    /// Number of token IDs in the compressed data
+    pub n_parsed_tokens: usize,
+    /// Total length in bytes of the strings once decompressed
+    pub uncompressed_bytes: usize,
+    /// Total space in bytes used by the collection, as returned by `space_used()`
+    pub compressed_bytes: usize,
+    /// Average compressed size in bytes of a string, dictionary included
+    pub bytes_per_string: f64,
+    /// Ratio of the uncompressed size to the compressed size
+    pub compression_ratio: f64,
+    /// Number of strings whose token IDs take more bytes than the string itself
+    pub expanded_strings: usize,
+    /// Number of occurrences of each token in the compressed data, indexed by token ID
+    pub token_usage: Vec<usize>,
+}
+
+/// Counts the occurrences of each of the `n_tokens` tokens in the compressed data
+pub(crate) fn token_usage<T: TokenId>(compressed_data: &[T], n_tokens: usize) -> Vec<usize> {
+    let mut usage = vec![0; n_tokens];
+    for &token_id in compressed_data {
+        usage[token_id.index()] += 1;
+    }
+    usage
+}
+
+/// Collects the statistics of a collection from its parts
+pub(crate) fn collect<T: TokenId>(
+    compressed_data: &[T],
+    string_boundaries: &StringBoundaries,
+    token_boundaries: &[u32],
+    compressed_bytes: usize,
+) -> CompressionStats {
+    let n_strings = string_boundaries.len().saturating_sub(1);
+    let n_tokens = token_boundaries.len().saturating_sub(1);
+    let token_len = |id: usize| (token_boundaries[id + 1] - token_boundaries[id]) as usize;
+
+    let token_usage = token_usage(compressed_data, n_tokens);
+    let uncompressed_bytes = token_usage.iter().enumerate().map(|(id, &count)| count * token_len(id)).sum();
+
+    let expanded_strings = (0..n_strings)
+        .filter(|&index| {
+            let tokens = &compressed_data[string_boundaries.range(index)];
+            let length: usize = tokens.iter().map(|&token_id| token_len(token_id.index())).sum();
+            tokens.len() * T::SIZE > length
+        })
+        .count();
+
+    let ratio = |numerator: usize, denominator: usize| match denominator {
+        0 => 0.0,
+        _ => numerator as f64 / denominator as f64,
+    };
+
+    CompressionStats {
+        n_strings,
+        n_tokens,
+        n_merged_tokens: n_tokens.saturating_sub(N_BYTE_TOKENS),
+        average_token_length: ratio(token_boundaries.last().map_or(0, |&end| end as usize), n_tokens),
+        n_parsed_tokens: compressed_data.len(),
+        uncompressed_bytes,
+        compressed_bytes,
+        bytes_per_string: ratio(compressed_bytes, n_strings),
+        compression_ratio: ratio(uncompressed_bytes, compressed_bytes),
+        expanded_strings,
+        token_usage,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compressor::testing::{self, compressed};
+    use crate::compressor::{OnPair, OnPair16, OnPairConfig};
+
+    /// Token boundaries of the single-byte tokens followed by "ab" (256) and "abc" (257)
+    fn token_boundaries() -> Vec<u32> {
+        let mut token_boundaries: Vec<u32> = (0..=256).collect();
+        token_boundaries.extend([258, 261]);
+        token_boundaries
+    }
+
+    fn check_fixed<T: TokenId>(expanded_strings: usize) {
+        // "abcab", "", "x" and "abc"
+        let compressed_data: Vec<T> = [257, 256, b'x' as usize, 257].into_iter().map(T::from_index).collect();
+        let string_boundaries = StringBoundaries::from(vec![0, 2, 2, 3, 4]);
+        let stats = collect(&compressed_data, &string_boundaries, &token_boundaries(), 36);
+
+        assert_eq!(stats.n_strings, 4);
+        assert_eq!(stats.n_tokens, 258);
+        assert_eq!(stats.n_merged_tokens, 2);
+        assert_eq!(stats.average_token_length, 261.0 / 258.0);
+        assert_eq!(stats.n_parsed_tokens, 4);
+        assert_eq!(stats.uncompressed_bytes, 9);
+        assert_eq!(stats.compressed_bytes, 36);
+        assert_eq!(stats.bytes_per_string, 9.0);
+        assert_eq!(stats.compression_ratio, 0.25);
+        assert_eq!(stats.expanded_strings, expanded_strings);
+
+        assert_eq!(stats.token_usage.len(), 258);
+        assert_eq!(stats.token_usage.iter().sum::<usize>(), stats.n_parsed_tokens);
+        assert_eq!((stats.token_usage[b'x' as usize], stats.token_usage[256], stats.token_usage[257]), (1, 1, 2));
+    }
+
+    #[test]
+    fn fixed_collection() {
+        // With 2-byte IDs only "x" takes more space than its bytes; with 4-byte IDs "abcab" and "abc" do too
+        check_fixed::<u16>(1);
+        check_fixed::<u32>(3);
+    }
+
+    #[test]
+    fn empty_collection() {
+        let stats = collect::<u16>(&[], &StringBoundaries::from(vec![0]), &token_boundaries(), 0);
+        assert_eq!((stats.n_strings, stats.n_parsed_tokens, stats.uncompressed_bytes, stats.expanded_strings), (0, 0, 0, 0));
+        assert_eq!((stats.bytes_per_string, stats.compression_ratio), (0.0, 0.0));
+        assert!(stats.token_usage.iter().all(|&count| count == 0));
+    }
+
+    fn check_collection(stats: CompressionStats, strings: &[String], num_tokens: usize, space_used: usize) {
+        let uncompressed_bytes: usize = strings.iter().map(String::len).sum();
+        assert_eq!(stats.n_strings, strings.len());
+        assert_eq!(stats.n_tokens, num_tokens);
+        assert_eq!(stats.n_merged_tokens, num_tokens - N_BYTE_TOKENS);
+        assert_eq!(stats.uncompressed_bytes, uncompressed_bytes);
+        assert_eq!(stats.compressed_bytes, space_used);
+        assert_eq!(stats.compression_ratio, uncompressed_bytes as f64 / space_used as f64);
+        assert_eq!(stats.token_usage.len(), num_tokens);
+        assert_eq!(stats.token_usage.iter().sum::<usize>(), stats.n_parsed_tokens);
+        // A single-byte string is stored as one 2-byte token ID, so it always expands
+        let single_bytes = strings.iter().filter(|string| string.len() == 1).count();
+        assert!(stats.expanded_strings >= single_bytes && stats.expanded_strings < strings.len());
+    }
+
+    #[test]
+    fn trained_collection() {
+        let mut strings = testing::urls(200, 20);
+        strings.extend(["a", "", "b"].map(str::to_owned));
+        let config = OnPairConfig::new(3).with_seed(20);
+
+        let onpair = compressed::<OnPair>(&strings, config);
+        check_collection(onpair.stats(), &strings, onpair.num_tokens(), onpair.space_used());
+        let onpair16 = compressed::<OnPair16>(&strings, config);
+        check_collection(onpair16.stats(), &strings, onpair16.num_tokens(), onpair16.space_used());
+    }
+}
@@ -0,0 +1,204 @@
+//! Storage layouts of the token stream
+//!
+//! `OnPairBase` keeps the token IDs of its strings either in a plain vector, one
+//! ID per element (`OnPair`, `OnPair32`), or bit-packed at the narrowest width
+//! that addresses the dictionary (`OnPairPacked`). Both layouts expose the same
+//! operations, so the collection is written once for both.
+
+use crate::compressor::format;
+use crate::compressor::onpair::OnPairBase;
+use crate::compressor::packed::{self, PackedTokens};
+use crate::compressor::token::TokenId;
+use std::io::{self, Read, Write};
+use std::ops::Range;
+
+/// Storage layout of the token IDs of a collection
+///
+/// This trait is sealed: it is implemented by `Vec<T>` and by `PackedTokens` only.
+pub trait TokenStorage<T: TokenId>: private::Storage<T> {}
+
+impl<T: TokenId> TokenStorage<T> for Vec<T> {}
+
+impl TokenStorage<u16> for PackedTokens {}
+
+pub(crate) mod private {
+    use super::*;
+
+    /// Operations of a token storage, hidden from the public API
+    pub trait Storage<T: TokenId>: Send + Sync + Sized {
+        /// Variant tag of the collections with this layout in the serialized format
+        const VARIANT: u8;
+
+        /// Creates an empty storage for the token IDs of a dictionary of `n_tokens` tokens
+        fn for_tokens(n_tokens: usize) -> Self;
+
+        /// Removes every token ID, making room for the IDs of a dictionary of `n_tokens` tokens
+        fn reset(&mut self, n_tokens: usize);
+
+        /// Reserves room for at least `additional` more token IDs
+        fn reserve_tokens(&mut self, additional: usize);
+
+        /// Returns the number of token IDs
+        fn n_tokens(&self) -> usize;
+
+        /// Appends the token IDs produced by `encode`, with the end positions of the strings
+        ///
+        /// `encode` follows the contract of the dictionaries' `encode` methods. The positions
+        /// it appends to `string_boundaries` end up relative to the start of the storage.
+        fn append_with<F>(&mut self, string_boundaries: &mut Vec<usize>, encode: F)
+        where
+            F: FnOnce(&mut Vec<T>, &mut Vec<usize>);
+
+        /// Returns an iterator over the token IDs in `range`
+        fn token_ids(&self, range: Range<usize>) -> impl Iterator<Item = T> + '_;
+
+        /// Returns the size in bytes of the stored token IDs
+        fn payload_size(&self) -> usize;
+
+        /// Returns the heap memory (in bytes) allocated for the token IDs, including spare capacity
+        fn heap_size(&self) -> usize;
+
+        /// Shrinks the storage to fit its current contents
+        fn shrink(&mut self);
+
+        /// Writes the four sections of a collection with this token stream
+        fn write_sections<W: Write>(
+            &self,
+            writer: &mut W,
+            dictionary: &[u8],
+            token_boundaries: &[u32],
+            string_boundaries: impl ExactSizeIterator<Item = usize>,
+        ) -> io::Result<()>;
+
+        /// Reads and validates a collection with this layout
+        fn read_collection<R: Read>(reader: &mut R) -> io::Result<OnPairBase<T, Self>>
+        where
+            Self: super::TokenStorage<T>;
+    }
+}
+
+impl<T: TokenId> private::Storage<T> for Vec<T> {
+    const VARIANT: u8 = T::VARIANT;
+
+    fn for_tokens(_n_tokens: usize) -> Self {
+        Vec::new()
+    }
+
+    fn reset(&mut self, _n_tokens: usize) {
+        self.clear();
+    }
+
+    fn reserve_tokens(&mut self, additional: usize) {
+        self.reserve(additional);
+    }
+
+    #[inline]
+    fn n_tokens(&self) -> usize {
+        self.len()
+    }
+
+    fn append_with<F>(&mut self, string_boundaries: &mut Vec<usize>, encode: F)
+    where
+        F: FnOnce(&mut Vec<T>, &mut Vec<usize>),
+    {
+        // The positions are already absolute, starting with the previous end of the token stream
+        encode(self, string_boundaries);
+    }
+
+    #[inline]
+    fn token_ids(&self, range: Range<usize>) -> impl Iterator<Item = T> + '_ {
+        self[range].iter().copied()
+    }
+
+    fn payload_size(&self) -> usize {
+        self.len() * T::SIZE
+    }
+
+    fn heap_size(&self) -> usize {
+        self.capacity() * T::SIZE
+    }
+
+    fn shrink(&mut self) {
+        self.shrink_to_fit();
+    }
+
+    fn write_sections<W: Write>(
+        &self,
+        writer: &mut W,
+        dictionary: &[u8],
+        token_boundaries: &[u32],
+        string_boundaries: impl ExactSizeIterator<Item = usize>,
+    ) -> io::Result<()> {
+        format::write_sections(writer, dictionary, token_boundaries, string_boundaries, self)
+    }
+
+    fn read_collection<R: Read>(reader: &mut R) -> io::Result<OnPairBase<T, Self>> {
+        format::read_sections(reader, T::VARIANT, usize::MAX).map(OnPairBase::from_sections)
+    }
+}
+
+impl private::Storage<u16> for PackedTokens {
+    const VARIANT: u8 = format::VARIANT_ONPAIR_PACKED;
+
+    fn for_tokens(n_tokens: usize) -> Self {
+        PackedTokens::new(packed::code_width(n_tokens))
+    }
+
+    fn reset(&mut self, n_tokens: usize) {
+        PackedTokens::reset(self, packed::code_width(n_tokens));
+    }
+
+    fn reserve_tokens(&mut self, additional: usize) {
+        self.reserve(additional);
+    }
+
+    #[inline]
+    fn n_tokens(&self) -> usize {
+        self.len()
+    }
+
+    fn append_with<F>(&mut self, string_boundaries: &mut Vec<usize>, encode: F)
+    where
+        F: FnOnce(&mut Vec<u16>, &mut Vec<usize>),
+    {
+        // The token IDs are parsed into a temporary buffer and then packed
+        let start = string_boundaries.len();
+        let mut token_ids = Vec::new();
+        encode(&mut token_ids, string_boundaries);
+
+        let offset = self.len();
+        string_boundaries[start..].iter_mut().for_each(|boundary| *boundary += offset);
+        self.extend_from_slice(&token_ids);
+    }
+
+    #[inline]
+    fn token_ids(&self, range: Range<usize>) -> impl Iterator<Item = u16> + '_ {
+        self.iter(range)
+    }
+
+    fn payload_size(&self) -> usize {
+        self.as_bytes().len()
+    }
+
+    fn heap_size(&self) -> usize {
+        PackedTokens::heap_size(self)
+    }
+
+    fn shrink(&mut self) {
+        self.shrink_to_fit();
+    }
+
+    fn write_sections<W: Write>(
+        &self,
+        writer: &mut W,
+        dictionary: &[u8],
+        token_boundaries: &[u32],
+        string_boundaries: impl ExactSizeIterator<Item = usize>,
+    ) -> io::Result<()> {
+        format::write_packed_sections(writer, dictionary, token_boundaries, string_boundaries, self.as_bytes())
+    }
+
+    fn read_collection<R: Read>(reader: &mut R) -> io::Result<OnPairBase<u16, Self>> {
+        format::read_packed_sections(reader, format::VARIANT_ONPAIR_PACKED).map(OnPairBase::from_sections)
+    }
+}
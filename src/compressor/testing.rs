@@ -0,0 +1,101 @@
+//! Test data shared by the unit tests
+
+use crate::compressor::{CompressedStrings, OnPair, OnPair16, OnPair32, OnPairConfig, OnPairPacked, StringCompressor, TrainingReport};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::io;
+
+/// Returns `n` URL-like strings built from a few hosts and paths, so that training merges long tokens
+pub(crate) fn urls(n: usize, seed: u64) -> Vec<String> {
+    const HOSTS: [&str; 4] = ["example.com", "www.example.org", "api.service.io", "cdn.static.net"];
+    const PATHS: [&str; 5] = ["users", "items", "orders", "search", "images/thumbnails"];
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    (0..n)
+        .map(|_| {
+            let host = HOSTS[rng.gen_range(0..HOSTS.len())];
+            let path = PATHS[rng.gen_range(0..PATHS.len())];
+            format!("https://{}/{}/{}?page={}", host, path, rng.gen_range(0..1000), rng.gen_range(0..20))
+        })
+        .collect()
+}
+
+/// Copies a serialized collection into 8-byte aligned memory, as required by the views
+pub(crate) fn aligned(bytes: &[u8]) -> Vec<u64> {
+    let mut words = vec![0u64; bytes.len().div_ceil(8)];
+    for (word, chunk) in words.iter_mut().zip(bytes.chunks(8)) {
+        let mut buffer = [0u8; 8];
+        buffer[..chunk.len()].copy_from_slice(chunk);
+        *word = u64::from_ne_bytes(buffer);
+    }
+    words
+}
+
+/// Returns the first `len` bytes of aligned memory
+pub(crate) fn blob(words: &[u64], len: usize) -> &[u8] {
+    assert!(len <= words.len() * 8);
+    unsafe { std::slice::from_raw_parts(words.as_ptr().cast(), len) }
+}
+
+/// Owned collections that the tests build from a configuration and serialize
+pub(crate) trait Collection: StringCompressor {
+    fn with_config(config: OnPairConfig) -> Self;
+
+    fn serialize(&self, bytes: &mut Vec<u8>) -> io::Result<()>;
+
+    fn deserialize(bytes: &[u8]) -> io::Result<Self>;
+
+    fn num_tokens(&self) -> usize;
+
+    fn training_report(&self) -> Option<TrainingReport>;
+}
+
+macro_rules! impl_collection {
+    ($($ty:ty),*) => {$(
+        impl Collection for $ty {
+            fn with_config(config: OnPairConfig) -> Self {
+                <$ty>::with_config(config)
+            }
+
+            fn serialize(&self, bytes: &mut Vec<u8>) -> io::Result<()> {
+                <$ty>::serialize(self, bytes)
+            }
+
+            fn deserialize(bytes: &[u8]) -> io::Result<Self> {
+                <$ty>::deserialize(bytes)
+            }
+
+            fn num_tokens(&self) -> usize {
+                <$ty>::num_tokens(self)
+            }
+
+            fn training_report(&self) -> Option<TrainingReport> {
+                <$ty>::training_report(self)
+            }
+        }
+    )*};
+}
+
+impl_collection!(OnPair, OnPair16, OnPair32, OnPairPacked);
+
+/// Compresses `strings` with a collection trained with `config`
+pub(crate) fn compressed<C: Collection>(strings: &[String], config: OnPairConfig) -> C {
+    let mut collection = C::with_config(config);
+    collection.compress_strings(strings);
+    collection
+}
+
+/// Serializes a collection into a byte vector
+pub(crate) fn serialized<C: Collection>(collection: &C) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    collection.serialize(&mut bytes).unwrap();
+    bytes
+}
+
+/// Checks that a collection holds exactly `expected`
+pub(crate) fn assert_strings<C: CompressedStrings>(collection: &C, expected: &[String]) {
+    assert_eq!(collection.len(), expected.len());
+    for (index, string) in expected.iter().enumerate() {
+        assert_eq!(collection.get(index), string.as_bytes(), "string {}", index);
+    }
+}
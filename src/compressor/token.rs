@@ -0,0 +1,72 @@
+//! Integer types used to store token IDs
+
+use crate::compressor::config::{MAX_TOKENS_U16, MAX_TOKENS_U32};
+use crate::compressor::format::{VARIANT_ONPAIR, VARIANT_ONPAIR32};
+use std::hash::Hash;
+
+/// Unsigned integer type storing the token IDs of a variant (`u16` or `u32`)
+///
+/// `OnPairBase` and `OnPairDictionaryBase` are generic over this trait, which is sealed:
+/// it is implemented for `u16` (`OnPair`) and `u32` (`OnPair32`) only.
+pub trait TokenId: private::Sealed + Copy + Eq + Ord + Hash + Send + Sync + 'static {
+    /// Size in bytes of a token ID in memory and in the serialized token stream
+    const SIZE: usize;
+
+    /// Maximum number of tokens of a dictionary with this token ID type
+    const MAX_TOKENS: usize;
+
+    /// Variant tag of the collections with this token ID type in the serialized format
+    const VARIANT: u8;
+
+    /// Converts a token index, which must fit in the type, into a token ID
+    fn from_index(index: usize) -> Self;
+
+    /// Returns the token ID as an index into the token boundaries
+    fn index(self) -> usize;
+
+    /// Appends the little-endian representation of the token ID
+    fn write_le(self, bytes: &mut Vec<u8>);
+
+    /// Reads a token ID from exactly `SIZE` little-endian bytes
+    fn read_le(bytes: &[u8]) -> Self;
+}
+
+mod private {
+    /// Prevents `TokenId` from being implemented outside of this crate
+    pub trait Sealed {}
+
+    impl Sealed for u16 {}
+    impl Sealed for u32 {}
+}
+
+macro_rules! impl_token_id {
+    ($($ty:ty => $max_tokens:expr, $variant:expr);*) => {$(
+        impl TokenId for $ty {
+            const SIZE: usize = std::mem::size_of::<$ty>();
+            const MAX_TOKENS: usize = $max_tokens;
+            const VARIANT: u8 = $variant;
+
+            #[inline(always)]
+            fn from_index(index: usize) -> Self {
+                index as $ty
+            }
+
+            #[inline(always)]
+            fn index(self) -> usize {
+                self as usize
+            }
+
+            #[inline]
+            fn write_le(self, bytes: &mut Vec<u8>) {
+                bytes.extend_from_slice(&self.to_le_bytes());
+            }
+
+            #[inline]
+            fn read_le(bytes: &[u8]) -> Self {
+                <$ty>::from_le_bytes(bytes.try_into().unwrap())
+            }
+        }
+    )*};
+}
+
+impl_token_id!(u16 => MAX_TOKENS_U16, VARIANT_ONPAIR; u32 => MAX_TOKENS_U32, VARIANT_ONPAIR32);
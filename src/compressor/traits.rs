@@ -0,0 +1,243 @@
+//! Common interfaces shared by the OnPair compressors
+//!
+//! `CompressedStrings` covers read-only random access and is implemented by both
+//! the owned compressors and the zero-copy views. `StringCompressor` adds
+//! construction and compression, so storage layers and benchmarks can be written
+//! once and instantiated with any variant.
+
+use crate::compressor::{OnPair, OnPair16, OnPair16View, OnPair32, OnPair32View, OnPairPacked, OnPairView, SizeReport};
+
+/// Read-only random access to a compressed collection of strings
+pub trait CompressedStrings {
+    /// Returns the number of strings in the collection
+    fn len(&self) -> usize;
+
+    /// Returns `true` if the collection contains no strings
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the length in bytes of a specific string once decompressed
+    fn decompressed_len(&self, index: usize) -> usize;
+
+    /// Decompresses a specific string by index, appending it to `buffer`
+    ///
+    /// Returns the number of bytes appended.
+    fn decompress_into(&self, index: usize, buffer: &mut Vec<u8>) -> usize;
+
+    /// Returns a specific string by index as a newly allocated vector
+    fn get(&self, index: usize) -> Vec<u8> {
+        let mut string = Vec::new();
+        self.decompress_into(index, &mut string);
+        string
+    }
+
+    /// Decompresses a specific string by index using the 16-byte fast copy
+    ///
+    /// # Safety
+    /// The buffer must have at least `decompressed_len(index) + 16` bytes.
+    unsafe fn decompress_string(&self, index: usize, buffer: &mut [u8]) -> usize;
+}
+
+/// Compressor for collections of short strings
+pub trait StringCompressor: CompressedStrings + Sized {
+    /// Creates a new compressor instance
+    fn new(threshold: u16) -> Self;
+
+    /// Creates a new compressor with capacity hints for better memory allocation
+    fn with_capacity(threshold: u16, n_strings: usize, n_bytes: usize) -> Self;
+
+    /// Compresses a collection of strings
+    fn compress_strings<S: AsRef<str>>(&mut self, strings: &[S]);
+
+    /// Compresses pre-flattened byte data with end positions
+    fn compress_bytes(&mut self, data: &[u8], end_positions: &[usize]);
+
+    /// Decompresses all strings, appending them to `buffer`
+    ///
+    /// Returns the number of bytes appended.
+    fn decompress_all_into(&self, buffer: &mut Vec<u8>) -> usize;
+
+    /// Decompresses all strings using the 16-byte fast copy
+    ///
+    /// # Safety
+    /// The buffer must have room for all decompressed strings plus 16 bytes.
+    unsafe fn decompress_all(&self, buffer: &mut [u8]) -> usize;
+
+    /// Returns the total space (in bytes) used by the compressed data, string boundaries and dictionary
+    fn space_used(&self) -> usize;
+
+    /// Returns the heap memory (in bytes) allocated by the collection, including spare capacity
+    fn heap_size(&self) -> usize;
+
+    /// Returns a breakdown of the memory used by the collection
+    fn size_report(&self) -> SizeReport;
+
+    /// Shrinks all internal buffers to fit their current contents
+    fn shrink_to_fit(&mut self);
+}
+
+macro_rules! impl_compressed_strings {
+    ($($ty:ty),*) => {$(
+        impl CompressedStrings for $ty {
+            #[inline]
+            fn len(&self) -> usize {
+                <$ty>::len(self)
+            }
+
+            #[inline]
+            fn decompressed_len(&self, index: usize) -> usize {
+                <$ty>::decompressed_len(self, index)
+            }
+
+            #[inline]
+            fn decompress_into(&self, index: usize, buffer: &mut Vec<u8>) -> usize {
+                <$ty>::decompress_into(self, index, buffer)
+            }
+
+            #[inline]
+            fn get(&self, index: usize) -> Vec<u8> {
+                <$ty>::get(self, index)
+            }
+
+            #[inline]
+            unsafe fn decompress_string(&self, index: usize, buffer: &mut [u8]) -> usize {
+                <$ty>::decompress_string(self, index, buffer)
+            }
+        }
+    )*};
+}
+
+macro_rules! impl_string_compressor {
+    ($($ty:ty),*) => {$(
+        impl StringCompressor for $ty {
+            fn new(threshold: u16) -> Self {
+                <$ty>::new(threshold)
+            }
+
+            fn with_capacity(threshold: u16, n_strings: usize, n_bytes: usize) -> Self {
+                <$ty>::with_capacity(threshold, n_strings, n_bytes)
+            }
+
+            fn compress_strings<S: AsRef<str>>(&mut self, strings: &[S]) {
+                <$ty>::compress_strings(self, strings)
+            }
+
+            fn compress_bytes(&mut self, data: &[u8], end_positions: &[usize]) {
+                <$ty>::compress_bytes(self, data, end_positions)
+            }
+
+            fn decompress_all_into(&self, buffer: &mut Vec<u8>) -> usize {
+                <$ty>::decompress_all_into(self, buffer)
+            }
+
+            unsafe fn decompress_all(&self, buffer: &mut [u8]) -> usize {
+                <$ty>::decompress_all(self, buffer)
+            }
+
+            fn space_used(&self) -> usize {
+                <$ty>::space_used(self)
+            }
+
+            fn heap_size(&self) -> usize {
+                <$ty>::heap_size(self)
+            }
+
+            fn size_report(&self) -> SizeReport {
+                <$ty>::size_report(self)
+            }
+
+            fn shrink_to_fit(&mut self) {
+                <$ty>::shrink_to_fit(self)
+            }
+        }
+    )*};
+}
+
+impl_compressed_strings!(OnPair, OnPair16, OnPair32, OnPairPacked, OnPairView<'_>, OnPair16View<'_>, OnPair32View<'_>);
+impl_string_compressor!(OnPair, OnPair16, OnPair32, OnPairPacked);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compressor::onpair::flatten_strings;
+    use crate::compressor::testing::{self, serialized, Collection};
+
+    /// Checks the read-only interface against the expected strings
+    fn check_strings<C: CompressedStrings>(collection: &C, strings: &[String]) {
+        assert_eq!(collection.len(), strings.len());
+        assert_eq!(collection.is_empty(), strings.is_empty());
+
+        let mut buffer = b"prefix".to_vec();
+        for (index, string) in strings.iter().enumerate() {
+            assert_eq!(collection.decompressed_len(index), string.len());
+            assert_eq!(collection.get(index), string.as_bytes());
+
+            // Decompression appends to the buffer
+            let start = buffer.len();
+            assert_eq!(collection.decompress_into(index, &mut buffer), string.len());
+            assert_eq!(&buffer[start..], string.as_bytes());
+
+            let mut fast = vec![0; string.len() + 16];
+            assert_eq!(unsafe { collection.decompress_string(index, &mut fast) }, string.len());
+            assert_eq!(&fast[..string.len()], string.as_bytes());
+        }
+    }
+
+    /// Checks compression and the read-only interface of an owned collection, and returns it serialized
+    fn check_compressor<C: Collection>(strings: &[String]) -> Vec<u8> {
+        let (data, end_positions) = flatten_strings(strings);
+
+        let mut compressor = C::new(3);
+        compressor.compress_strings(strings);
+        check_strings(&compressor, strings);
+
+        let mut from_bytes = C::with_capacity(3, strings.len(), data.len());
+        from_bytes.compress_bytes(&data, &end_positions);
+        check_strings(&from_bytes, strings);
+
+        let mut all = b"prefix".to_vec();
+        assert_eq!(compressor.decompress_all_into(&mut all), data.len());
+        assert_eq!(&all[6..], &data[..]);
+        let mut fast = vec![0; data.len() + 16];
+        assert_eq!(unsafe { compressor.decompress_all(&mut fast) }, data.len());
+        assert_eq!(&fast[..data.len()], &data[..]);
+
+        assert_eq!(compressor.size_report().total(), compressor.space_used());
+        assert!(compressor.heap_size() >= compressor.space_used());
+        compressor.shrink_to_fit();
+        check_strings(&compressor, strings);
+
+        serialized(&compressor)
+    }
+
+    fn check_all(strings: &[String]) {
+        let bytes = check_compressor::<OnPair>(strings);
+        let words = testing::aligned(&bytes);
+        check_strings(&OnPairView::new(testing::blob(&words, bytes.len())).unwrap(), strings);
+
+        let bytes = check_compressor::<OnPair16>(strings);
+        let words = testing::aligned(&bytes);
+        check_strings(&OnPair16View::new(testing::blob(&words, bytes.len())).unwrap(), strings);
+
+        let bytes = check_compressor::<OnPair32>(strings);
+        let words = testing::aligned(&bytes);
+        check_strings(&OnPair32View::new(testing::blob(&words, bytes.len())).unwrap(), strings);
+
+        // Packed collections have no view
+        check_compressor::<OnPairPacked>(strings);
+    }
+
+    #[test]
+    fn every_implementor() {
+        let mut strings = testing::urls(100, 10);
+        strings[5].clear();
+        check_all(&strings);
+    }
+
+    #[test]
+    fn every_implementor_on_empty_collections() {
+        check_all(&[]);
+        check_all(&[String::new()]);
+    }
+}
@@ -0,0 +1,240 @@
+//! Zero-copy read-only views over serialized collections
+//!
+//! A view borrows a blob produced by `serialize` (typically a memory-mapped file)
+//! and decompresses strings directly from the serialized dictionary, token
+//! boundaries and token stream, without materializing any `Vec`.
+
+use crate::compressor::format::{self, BorrowedSections};
+use crate::compressor::onpair::decoded_len;
+use crate::compressor::token::TokenId;
+use crate::compressor::{onpair, onpair16};
+use std::io;
+
+/// Read-only view over a serialized `OnPair` collection
+#[derive(Clone, Copy)]
+pub struct OnPairView<'a> {
+    sections: BorrowedSections<'a>,
+}
+
+/// Read-only view over a serialized `OnPair16` collection
+#[derive(Clone, Copy)]
+pub struct OnPair16View<'a> {
+    sections: BorrowedSections<'a>,
+}
+
+/// Read-only view over a serialized `OnPair32` collection
+#[derive(Clone, Copy)]
+pub struct OnPair32View<'a> {
+    sections: BorrowedSections<'a, u32>,
+}
+
+macro_rules! impl_view {
+    ($view:ident, $token:ty, $variant:expr, $max_token_length:expr, $decode:path) => {
+        impl<'a> $view<'a> {
+            /// Creates a view over a serialized collection
+            ///
+            /// The blob must start at an 8-byte aligned address, which holds for memory-mapped
+            /// files and for buffers allocated with 8-byte alignment. Every section is validated,
+            /// which touches the whole blob once.
+            pub fn new(bytes: &'a [u8]) -> io::Result<Self> {
+                let sections = format::borrow_sections(bytes, $variant, $max_token_length, true)?;
+                Ok(Self { sections })
+            }
+
+            /// Creates a view over a serialized collection, checking only the header
+            ///
+            /// # Safety
+            /// The blob must have been produced by `serialize` for the same variant and must
+            /// not have been modified since; decompressing from a corrupted blob is undefined behavior.
+            pub unsafe fn new_unchecked(bytes: &'a [u8]) -> io::Result<Self> {
+                let sections = format::borrow_sections(bytes, $variant, $max_token_length, false)?;
+                Ok(Self { sections })
+            }
+
+            /// Returns the number of strings in the collection
+            #[inline]
+            pub fn len(&self) -> usize {
+                self.sections.string_boundaries.len().saturating_sub(1)
+            }
+
+            /// Returns `true` if the collection contains no strings
+            #[inline]
+            pub fn is_empty(&self) -> bool {
+                self.len() == 0
+            }
+
+            /// Returns the merging frequency threshold the collection was trained with
+            #[inline]
+            pub fn threshold(&self) -> u16 {
+                self.sections.threshold
+            }
+
+            /// Returns the length in bytes of a specific string once decompressed
+            #[inline]
+            pub fn decompressed_len(&self, index: usize) -> usize {
+                decoded_len(self.tokens(index).iter().copied(), self.sections.token_boundaries)
+            }
+
+            /// Decompresses a specific string by index, appending it to `buffer`
+            ///
+            /// Returns the number of bytes appended.
+            #[inline]
+            pub fn decompress_into(&self, index: usize, buffer: &mut Vec<u8>) -> usize {
+                decompress_into(&self.sections, $decode, self.tokens(index), buffer)
+            }
+
+            /// Returns a specific string by index as a newly allocated vector
+            #[inline]
+            pub fn get(&self, index: usize) -> Vec<u8> {
+                let mut string = Vec::new();
+                self.decompress_into(index, &mut string);
+                string
+            }
+
+            /// Decompresses a specific string by index
+            ///
+            /// # Safety
+            /// The serialized dictionary is padded, so reads stay within the blob, but the
+            /// buffer must have at least 16 bytes of space beyond the decompressed data,
+            /// exactly as for the owned compressor, or undefined behavior will occur.
+            #[inline]
+            pub unsafe fn decompress_string(&self, index: usize, buffer: &mut [u8]) -> usize {
+                $decode(self.tokens(index), self.sections.dictionary.as_ptr(), self.sections.token_boundaries, buffer.as_mut_ptr())
+            }
+
+            /// Returns the token IDs of a specific string
+            #[inline]
+            fn tokens(&self, index: usize) -> &'a [$token] {
+                let string_start = self.sections.string_boundaries[index] as usize;
+                let string_end = self.sections.string_boundaries[index + 1] as usize;
+                &self.sections.compressed_data[string_start..string_end]
+            }
+
+            /// Returns an iterator over the decompressed strings
+            pub fn iter(&self) -> ViewIter<'a, $token> {
+                ViewIter {
+                    sections: self.sections,
+                    decode: $decode,
+                    index: 0,
+                }
+            }
+        }
+
+        impl<'a> IntoIterator for &$view<'a> {
+            type Item = Vec<u8>;
+            type IntoIter = ViewIter<'a, $token>;
+
+            fn into_iter(self) -> Self::IntoIter {
+                self.iter()
+            }
+        }
+    };
+}
+
+impl_view!(OnPairView, u16, format::VARIANT_ONPAIR, usize::MAX, onpair::decode_tokens);
+impl_view!(OnPair16View, u16, format::VARIANT_ONPAIR16, onpair16::MAX_LENGTH, onpair16::decode_tokens);
+impl_view!(OnPair32View, u32, format::VARIANT_ONPAIR32, usize::MAX, onpair::decode_tokens);
+
+type DecodeFn<T> = unsafe fn(&[T], *const u8, &[u32], *mut u8) -> usize;
+
+/// Appends the decoded tokens to `buffer`, reserving room for the 16-byte fast copy
+fn decompress_into<T: TokenId>(
+    sections: &BorrowedSections<'_, T>,
+    decode: DecodeFn<T>,
+    tokens: &[T],
+    buffer: &mut Vec<u8>,
+) -> usize {
+    let length = decoded_len(tokens.iter().copied(), sections.token_boundaries);
+    buffer.reserve(length + 16);
+
+    unsafe {
+        let dst = buffer.as_mut_ptr().add(buffer.len());
+        let size = decode(tokens, sections.dictionary.as_ptr(), sections.token_boundaries, dst);
+        buffer.set_len(buffer.len() + size);
+        size
+    }
+}
+
+/// Iterator over the decompressed strings of a view
+pub struct ViewIter<'a, T: TokenId = u16> {
+    sections: BorrowedSections<'a, T>,
+    decode: DecodeFn<T>,
+    index: usize,
+}
+
+impl<T: TokenId> Iterator for ViewIter<'_, T> {
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Vec<u8>> {
+        let boundaries = self.sections.string_boundaries;
+        if self.index + 1 >= boundaries.len() {
+            return None;
+        }
+
+        let string_start = boundaries[self.index] as usize;
+        let string_end = boundaries[self.index + 1] as usize;
+        let tokens = &self.sections.compressed_data[string_start..string_end];
+        self.index += 1;
+
+        let mut string = Vec::new();
+        decompress_into(&self.sections, self.decode, tokens, &mut string);
+        Some(string)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.sections.string_boundaries.len().saturating_sub(self.index + 1);
+        (remaining, Some(remaining))
+    }
+}
+
+impl<T: TokenId> ExactSizeIterator for ViewIter<'_, T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compressor::testing;
+    use crate::compressor::{CompressedStrings, OnPair, OnPair16, OnPairConfig};
+
+    /// Checks every way of decompressing the last string, which is the last token of the dictionary
+    fn assert_last_string<V: CompressedStrings>(view: &V, expected: &[u8]) {
+        let index = view.len() - 1;
+        assert_eq!(view.decompressed_len(index), expected.len());
+        assert_eq!(view.get(index), expected);
+
+        let mut buffer = vec![0u8; expected.len() + 16];
+        let size = unsafe { view.decompress_string(index, &mut buffer) };
+        assert_eq!(&buffer[..size], expected);
+    }
+
+    // The fast copy of the last token reads 16 bytes from its start, past the end of the
+    // dictionary bytes: under Miri this checks that the reads stay within the borrowed slice
+    #[test]
+    fn decompress_last_token_within_blob() {
+        let strings = testing::urls(40, 3);
+        let config = OnPairConfig::new(2).with_max_token_length(7).with_seed(3);
+
+        let mut compressor = OnPair::with_config(config);
+        compressor.compress_strings(&strings);
+        let last_token = compressor.token_bytes((compressor.num_tokens() - 1) as u16).to_vec();
+        compressor.push_bytes(&last_token);
+
+        let mut bytes = Vec::new();
+        compressor.serialize(&mut bytes).unwrap();
+        let words = testing::aligned(&bytes);
+        let view = OnPairView::new(testing::blob(&words, bytes.len())).unwrap();
+        assert_last_string(&view, &last_token);
+        assert_eq!(view.iter().last().unwrap(), last_token);
+
+        let mut compressor = OnPair16::with_config(config);
+        compressor.compress_strings(&strings);
+        let last_token = compressor.token_bytes((compressor.num_tokens() - 1) as u16).to_vec();
+        compressor.push_bytes(&last_token);
+
+        let mut bytes = Vec::new();
+        compressor.serialize(&mut bytes).unwrap();
+        let words = testing::aligned(&bytes);
+        let view = OnPair16View::new(testing::blob(&words, bytes.len())).unwrap();
+        assert_last_string(&view, &last_token);
+        assert_eq!(view.iter().last().unwrap(), last_token);
+    }
+}
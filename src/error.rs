@@ -0,0 +1,218 @@
+//! Error type for the fallible (`try_`) API
+
+use std::fmt;
+
+/// Errors reported by the fallible compression and decompression methods
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OnPairError {
+    /// The merging frequency threshold must be greater than 1
+    InvalidThreshold(u16),
+    /// A training parameter of `OnPairConfig` is out of range
+    InvalidConfig(&'static str),
+    /// `end_positions` is non-empty but does not start with 0
+    EndPositionsNotZeroBased,
+    /// `end_positions[index]` is smaller than `end_positions[index - 1]`
+    EndPositionsNotMonotonic { index: usize },
+    /// The last end position does not match the length of the data
+    EndPositionsLengthMismatch { last: usize, data_len: usize },
+    /// The string index is not smaller than the number of strings
+    IndexOutOfBounds { index: usize, len: usize },
+    /// The output buffer cannot hold the decompressed string plus the fast-copy slack
+    BufferTooSmall { required: usize, available: usize },
+    /// Strings cannot be encoded because no dictionary has been trained or provided
+    MissingDictionary,
+    /// The regular expression is invalid or its DFA cannot be built (`regex` feature)
+    InvalidRegex(String),
+}
+
+impl fmt::Display for OnPairError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            OnPairError::InvalidThreshold(threshold) => {
+                write!(f, "threshold must be greater than 1, got {}", threshold)
+            }
+            OnPairError::InvalidConfig(reason) => {
+                write!(f, "invalid configuration: {}", reason)
+            }
+            OnPairError::EndPositionsNotZeroBased => {
+                write!(f, "end positions must start with 0")
+            }
+            OnPairError::EndPositionsNotMonotonic { index } => {
+                write!(f, "end positions must be non-decreasing, but position {} decreases", index)
+            }
+            OnPairError::EndPositionsLengthMismatch { last, data_len } => {
+                write!(f, "last end position is {} but the data is {} bytes long", last, data_len)
+            }
+            OnPairError::IndexOutOfBounds { index, len } => {
+                write!(f, "string index {} is out of bounds for a collection of {} strings", index, len)
+            }
+            OnPairError::BufferTooSmall { required, available } => {
+                write!(f, "buffer of {} bytes is too small, {} bytes are required", available, required)
+            }
+            OnPairError::MissingDictionary => {
+                write!(f, "no dictionary has been trained or provided")
+            }
+            OnPairError::InvalidRegex(ref reason) => {
+                write!(f, "invalid regular expression: {}", reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for OnPairError {}
+
+/// Checks that the threshold is valid for training
+pub(crate) fn check_threshold(threshold: u16) -> Result<(), OnPairError> {
+    if threshold > 1 {
+        Ok(())
+    } else {
+        Err(OnPairError::InvalidThreshold(threshold))
+    }
+}
+
+/// Checks that `end_positions` is a prefix sum array spanning `data`
+///
+/// An empty `end_positions` describes an empty collection and is accepted for empty data.
+pub(crate) fn check_end_positions(data: &[u8], end_positions: &[usize]) -> Result<(), OnPairError> {
+    let Some(&first) = end_positions.first() else {
+        return match data.len() {
+            0 => Ok(()),
+            data_len => Err(OnPairError::EndPositionsLengthMismatch { last: 0, data_len }),
+        };
+    };
+
+    if first != 0 {
+        return Err(OnPairError::EndPositionsNotZeroBased);
+    }
+    if let Some(index) = end_positions.windows(2).position(|window| window[0] > window[1]) {
+        return Err(OnPairError::EndPositionsNotMonotonic { index: index + 1 });
+    }
+
+    let last = *end_positions.last().unwrap();
+    if last != data.len() {
+        return Err(OnPairError::EndPositionsLengthMismatch { last, data_len: data.len() });
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compressor::testing;
+    use crate::{OnPair, OnPair16};
+    use std::io;
+
+    #[test]
+    fn threshold() {
+        assert_eq!(check_threshold(0), Err(OnPairError::InvalidThreshold(0)));
+        assert_eq!(check_threshold(1), Err(OnPairError::InvalidThreshold(1)));
+        assert_eq!(check_threshold(2), Ok(()));
+        assert_eq!(OnPair::try_new(0).err(), Some(OnPairError::InvalidThreshold(0)));
+        assert_eq!(OnPair16::try_new(1).err(), Some(OnPairError::InvalidThreshold(1)));
+        assert!(OnPair::try_new(2).is_ok());
+    }
+
+    #[test]
+    fn end_positions() {
+        assert_eq!(check_end_positions(b"", &[]), Ok(()));
+        assert_eq!(check_end_positions(b"", &[0]), Ok(()));
+        assert_eq!(check_end_positions(b"abc", &[0, 1, 1, 3]), Ok(()));
+        assert_eq!(check_end_positions(b"abc", &[]), Err(OnPairError::EndPositionsLengthMismatch { last: 0, data_len: 3 }));
+        assert_eq!(check_end_positions(b"abc", &[1, 3]), Err(OnPairError::EndPositionsNotZeroBased));
+        assert_eq!(check_end_positions(b"abc", &[0, 2, 1, 3]), Err(OnPairError::EndPositionsNotMonotonic { index: 2 }));
+        assert_eq!(check_end_positions(b"abc", &[0, 2]), Err(OnPairError::EndPositionsLengthMismatch { last: 2, data_len: 3 }));
+        assert_eq!(check_end_positions(b"abc", &[0, 4]), Err(OnPairError::EndPositionsLengthMismatch { last: 4, data_len: 3 }));
+
+        let mut onpair = OnPair::new(3);
+        assert_eq!(onpair.try_compress_bytes(b"abc", &[0, 2, 1, 3]), Err(OnPairError::EndPositionsNotMonotonic { index: 2 }));
+        assert_eq!(onpair.try_compress_bytes(b"abc", &[0, 4]), Err(OnPairError::EndPositionsLengthMismatch { last: 4, data_len: 3 }));
+        assert!(onpair.is_empty());
+        assert_eq!(onpair.try_compress_bytes(b"abc", &[0, 1, 3]), Ok(()));
+        assert_eq!(onpair.try_extend_bytes(b"abc", &[1, 3]), Err(OnPairError::EndPositionsNotZeroBased));
+        assert_eq!(onpair.len(), 2);
+
+        let mut onpair16 = OnPair16::new(3);
+        assert_eq!(onpair16.try_compress_bytes(b"abc", &[0, 2]), Err(OnPairError::EndPositionsLengthMismatch { last: 2, data_len: 3 }));
+        assert_eq!(onpair16.try_compress_bytes(b"", &[]), Ok(()));
+        assert!(onpair16.is_empty());
+    }
+
+    #[test]
+    fn decompress_out_of_range() {
+        let strings = ["", "abc", "abcabcabc"];
+        let mut onpair = OnPair::new(2);
+        onpair.compress_strings(&strings);
+        let mut onpair16 = OnPair16::new(2);
+        onpair16.compress_strings(&strings);
+
+        for (index, string) in strings.iter().enumerate() {
+            let required = string.len() + 16;
+            let mut buffer = vec![0; required];
+            assert_eq!(onpair.try_decompress_string(index, &mut buffer), Ok(string.len()));
+            assert_eq!(&buffer[..string.len()], string.as_bytes());
+            assert_eq!(onpair16.try_decompress_string(index, &mut buffer), Ok(string.len()));
+
+            let error = OnPairError::BufferTooSmall { required, available: required - 1 };
+            assert_eq!(onpair.try_decompress_string(index, &mut buffer[..required - 1]), Err(error.clone()));
+            assert_eq!(onpair16.try_decompress_string(index, &mut buffer[..required - 1]), Err(error));
+        }
+
+        let mut buffer = vec![0; 64];
+        let error = OnPairError::IndexOutOfBounds { index: 3, len: 3 };
+        assert_eq!(onpair.try_decompress_string(3, &mut buffer), Err(error.clone()));
+        assert_eq!(onpair16.try_decompress_string(3, &mut buffer), Err(error));
+        assert_eq!(OnPair::new(3).try_decompress_string(0, &mut buffer), Err(OnPairError::IndexOutOfBounds { index: 0, len: 0 }));
+    }
+
+    #[test]
+    fn deserialize_truncated_or_corrupted() {
+        let strings = testing::urls(50, 16);
+        let mut onpair = OnPair::new(3);
+        onpair.compress_strings(&strings);
+        let mut bytes = Vec::new();
+        onpair.serialize(&mut bytes).unwrap();
+        let mut onpair16 = OnPair16::new(3);
+        onpair16.compress_strings(&strings);
+        let mut bytes16 = Vec::new();
+        onpair16.serialize(&mut bytes16).unwrap();
+
+        for cut in [0, 10, bytes.len() / 2, bytes.len() - 1] {
+            assert_eq!(OnPair::deserialize(&bytes[..cut]).err().map(|error| error.kind()), Some(io::ErrorKind::UnexpectedEof));
+        }
+        for cut in [0, 10, bytes16.len() / 2, bytes16.len() - 1] {
+            assert_eq!(OnPair16::deserialize(&bytes16[..cut]).err().map(|error| error.kind()), Some(io::ErrorKind::UnexpectedEof));
+        }
+
+        // Wrong magic, a threshold of 1, and a first token ID without a token
+        let mut corrupted = bytes.clone();
+        corrupted[0] ^= 0xFF;
+        assert_eq!(OnPair::deserialize(&corrupted[..]).err().map(|error| error.kind()), Some(io::ErrorKind::InvalidData));
+        let mut corrupted = bytes16.clone();
+        corrupted[8..10].copy_from_slice(&1u16.to_le_bytes());
+        assert_eq!(OnPair16::deserialize(&corrupted[..]).err().map(|error| error.kind()), Some(io::ErrorKind::InvalidData));
+        let mut corrupted = bytes16.clone();
+        let n_tokens = u64::from_le_bytes(bytes16[40..48].try_into().unwrap()) as usize;
+        let tokens = bytes16.len() - (2 * n_tokens).next_multiple_of(8);
+        corrupted[tokens..tokens + 2].copy_from_slice(&u16::MAX.to_le_bytes());
+        assert_eq!(OnPair16::deserialize(&corrupted[..]).err().map(|error| error.kind()), Some(io::ErrorKind::InvalidData));
+    }
+
+    #[test]
+    #[should_panic(expected = "Threshold must be greater than 1")]
+    fn new_with_invalid_threshold() {
+        OnPair::new(1);
+    }
+
+    #[test]
+    #[should_panic(expected = "Threshold must be greater than 1")]
+    fn new16_with_invalid_threshold() {
+        OnPair16::new(0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot append strings without a trained dictionary")]
+    fn extend16_without_dictionary() {
+        OnPair16::new(3).extend_bytes(b"abc", &[0, 3]);
+    }
+}
@@ -1,4 +1,10 @@
 pub mod compressor;
+pub mod error;
 pub mod lpm;
 
-pub use compressor::{OnPair, OnPair16};
\ No newline at end of file
+pub use compressor::{
+    Bitmap, CompressedStrings, CompressionStats, OnPair, OnPair16, OnPair16Dictionary, OnPair16View, OnPair32, OnPair32Dictionary, OnPair32View,
+    OnPairBase, OnPairConfig, OnPairDictionary, OnPairDictionaryBase, OnPairPacked, OnPairView, PackedTokens, Sample, SampleStrategy, Shuffle, SizeReport,
+    StringCompressor, TokenId, TokenStorage, TrainingReport,
+};
+pub use error::OnPairError;
@@ -3,8 +3,13 @@
 //! Provides efficient longest prefix matching using a hybrid approach:
 //! - Short matches (≤8 bytes): Direct hash table lookup in decreasing length order
 //! - Long matches (>8 bytes): Skip the first 8 bytes via hash table, then trie lookup for suffixes
+//!
+//! Provides both dynamic (training) and static (parsing) implementations.
 
+use crate::lpm::hash_map_heap_size;
+use ptr_hash::{bucket_fn::Linear, PtrHash, PtrHashParams};
 use rustc_hash::FxHashMap;
+use std::collections::VecDeque;
 
 /// Bit masks for extracting prefixes of different lengths (little-endian)
 const MASKS: [u64; 9] = [
@@ -36,7 +41,7 @@ struct TrieNode<V> {
 /// long patterns provide significant compression benefits.
 /// 
 /// Type Parameters
-/// - `V`: Token ID type (typically `u16` for OnPair)
+/// - `V`: Token ID type (`u16` for OnPair, `u32` for OnPair32)
 pub struct LongestPrefixMatcher<V> {
     /// Short patterns: (prefix, length) -> token ID
     short_match_lookup: FxHashMap<(u64, u8), V>,
@@ -50,7 +55,7 @@ pub struct LongestPrefixMatcher<V> {
 
 impl<V> LongestPrefixMatcher<V> 
 where 
-    V: Copy + Into<usize>,
+    V: Copy,
 {   
     /// Creates a new empty longest prefix matcher
     pub fn new() -> Self {
@@ -70,11 +75,11 @@ where
     pub fn insert(&mut self, entry: &[u8], id: V) {
         if entry.len() <= TRIE_PREFIX_LEN {
             // Short pattern: direct hash table lookup
-            let prefix = Self::bytes_to_u64_le(entry, entry.len());
+            let prefix = bytes_to_u64_le(entry, entry.len());
             self.short_match_lookup.insert((prefix, entry.len() as u8), id);
         } else {
             // Long pattern: use trie for suffix
-            let prefix = Self::bytes_to_u64_le(entry, TRIE_PREFIX_LEN);
+            let prefix = bytes_to_u64_le(entry, TRIE_PREFIX_LEN);
 
             // Find or create root node for this 8-byte prefix
             let mut node_idx = *self.long_match_roots.entry(prefix).or_insert_with(|| {
@@ -128,7 +133,7 @@ where
     pub fn find_longest_match(&self, data: &[u8]) -> Option<(V, usize)> {
         // Phase 1: Long pattern search (>8 bytes)
         if data.len() > TRIE_PREFIX_LEN {
-            let prefix = Self::bytes_to_u64_le(data, TRIE_PREFIX_LEN);
+            let prefix = bytes_to_u64_le(data, TRIE_PREFIX_LEN);
             
             if let Some(&root_idx) = self.long_match_roots.get(&prefix) {
                 let mut best_long_match = None;
@@ -171,7 +176,7 @@ where
 
         // Phase 2: Short pattern search (≤8 bytes) - longest to shortest
         for length in (1..=TRIE_PREFIX_LEN.min(data.len())).rev() {
-            let prefix = Self::bytes_to_u64_le(data, length);
+            let prefix = bytes_to_u64_le(data, length);
             
             if let Some(&id) = self.short_match_lookup.get(&(prefix, length as u8)) {
                 return Some((id, length));
@@ -181,14 +186,269 @@ where
         None
     }
 
-    /// Converts byte sequence to little-endian u64 with length masking
-    #[inline(always)]
-    fn bytes_to_u64_le(bytes: &[u8], len: usize) -> u64 {
-        let ptr = bytes.as_ptr();
-        let value = unsafe {
-            *(ptr as *const u64)
+    /// Returns an estimate of the heap memory (in bytes) allocated by the matcher
+    pub fn heap_size(&self) -> usize {
+        hash_map_heap_size(&self.short_match_lookup) +
+        hash_map_heap_size(&self.long_match_roots) +
+        self.node_pool.capacity() * std::mem::size_of::<TrieNode<V>>() +
+        self.node_pool.iter().map(|node| node.children.capacity() * std::mem::size_of::<(u8, u32)>()).sum::<usize>()
+    }
+
+    /// Converts dynamic matcher to optimized static representation
+    ///
+    /// The trie is flattened in breadth-first order, so that the children of every node
+    /// are contiguous and sorted by byte, and its roots are indexed by a perfect hash of
+    /// their 8-byte prefix. The static matcher returns the same matches as this one.
+    pub fn finalize(&self) -> StaticLongestPrefixMatcher<V> {
+        let mut nodes = Vec::with_capacity(self.node_pool.len());
+        let mut labels = Vec::with_capacity(self.node_pool.len());
+        let mut queue = VecDeque::with_capacity(self.node_pool.len());
+
+        // Roots come first, so that nodes are numbered in the order they leave the queue
+        let mut roots = Vec::with_capacity(self.long_match_roots.len());
+        for (&prefix, &root_idx) in self.long_match_roots.iter() {
+            roots.push((prefix, nodes.len() as u32));
+            nodes.push(StaticTrieNode { id: self.node_pool[root_idx as usize].id, first_child: 0, n_children: 0 });
+            labels.push(0);
+            queue.push_back(root_idx);
+        }
+
+        let mut static_idx = 0;
+        while let Some(node_idx) = queue.pop_front() {
+            let mut children = self.node_pool[node_idx as usize].children.clone();
+            children.sort_unstable_by_key(|&(byte, _)| byte);
+
+            nodes[static_idx].first_child = nodes.len() as u32;
+            nodes[static_idx].n_children = children.len() as u16;
+            for (byte, child_idx) in children {
+                nodes.push(StaticTrieNode { id: self.node_pool[child_idx as usize].id, first_child: 0, n_children: 0 });
+                labels.push(byte);
+                queue.push_back(child_idx);
+            }
+
+            static_idx += 1;
+        }
+
+        // Small dictionaries may have no patterns longer than 8 bytes to hash
+        let prefixes = roots.iter().map(|&(prefix, _)| prefix).collect::<Vec<_>>();
+        let (long_phf, long_roots) = if prefixes.is_empty() {
+            (None, Vec::new())
+        } else {
+            let mut params = PtrHashParams::default_fast();
+            params.remap = false;
+            let long_phf = PtrHash::new(&prefixes, params);
+            let max = prefixes.iter()
+                .map(|prefix| long_phf.index_no_remap(prefix))
+                .fold(0, |acc, idx| acc.max(idx));
+
+            let mut long_roots = vec![(0, NO_ROOT); max + 1];
+            for &(prefix, root) in &roots {
+                long_roots[long_phf.index_no_remap(&prefix)] = (prefix, root);
+            }
+
+            (Some(long_phf), long_roots)
         };
 
-        value & MASKS[len]
+        StaticLongestPrefixMatcher {
+            short_match_lookup: self.short_match_lookup.clone(),
+            long_phf,
+            long_roots,
+            nodes,
+            labels,
+        }
+    }
+}
+
+/// Marks the slots of the root table that the perfect hash maps no prefix to
+const NO_ROOT: u32 = u32::MAX;
+
+/// Node of the flattened trie
+///
+/// The children of a node are the `n_children` nodes starting at `first_child`,
+/// sorted by the byte that leads to them.
+#[derive(Clone, Copy, Debug)]
+struct StaticTrieNode<V> {
+    id: Option<V>,
+    first_child: u32,
+    n_children: u16,
+}
+
+/// Static (read-only) longest prefix matcher optimized for parsing phase
+///
+/// Same hybrid layout as `LongestPrefixMatcher`, with the trie roots indexed by a
+/// perfect hash function and the trie flattened into contiguous arrays, which
+/// avoids hash collisions and most pointer chasing during the parsing phase.
+pub struct StaticLongestPrefixMatcher<V> {
+    /// Short patterns: (prefix, length) -> token ID
+    short_match_lookup: FxHashMap<(u64, u8), V>,
+
+    /// Perfect hash of the 8-byte prefixes of long patterns
+    long_phf: Option<PtrHash<u64, Linear>>,
+
+    /// Perfect hash slot -> (8-byte prefix, root node), or `NO_ROOT` for unused slots
+    long_roots: Vec<(u64, u32)>,
+
+    /// Trie nodes in breadth-first order, with the byte leading to each node in `labels`
+    nodes: Vec<StaticTrieNode<V>>,
+    labels: Vec<u8>,
+}
+
+impl<V: Copy> StaticLongestPrefixMatcher<V> {
+    /// Finds the longest matching pattern for the given input data
+    ///
+    /// Returns the same match as `LongestPrefixMatcher::find_longest_match` on the
+    /// matcher this one was finalized from.
+    #[inline]
+    pub fn find_longest_match(&self, data: &[u8]) -> Option<(V, usize)> {
+        // Phase 1: Long pattern search (>8 bytes)
+        if data.len() > TRIE_PREFIX_LEN {
+            if let Some(long_match) = self.find_long_match(data) {
+                return Some(long_match);
+            }
+        }
+
+        // Phase 2: Short pattern search (≤8 bytes) - longest to shortest
+        let mut prefix = bytes_to_u64_le(data, TRIE_PREFIX_LEN);
+        for length in (1..=TRIE_PREFIX_LEN.min(data.len())).rev() {
+            prefix &= MASKS[length];
+            if let Some(&id) = self.short_match_lookup.get(&(prefix, length as u8)) {
+                return Some((id, length));
+            }
+        }
+
+        None
+    }
+
+    /// Returns an estimate of the heap memory (in bytes) allocated by the matcher
+    ///
+    /// The perfect hash function is not counted; it takes a few bits per long pattern prefix.
+    pub fn heap_size(&self) -> usize {
+        hash_map_heap_size(&self.short_match_lookup) +
+        self.long_roots.capacity() * std::mem::size_of::<(u64, u32)>() +
+        self.nodes.capacity() * std::mem::size_of::<StaticTrieNode<V>>() +
+        self.labels.capacity()
+    }
+
+    /// Finds the deepest pattern in the trie rooted at the first 8 bytes of `data`
+    #[inline]
+    fn find_long_match(&self, data: &[u8]) -> Option<(V, usize)> {
+        let prefix = bytes_to_u64_le(data, TRIE_PREFIX_LEN);
+        let index = self.long_phf.as_ref()?.index_no_remap(&prefix);
+
+        // Perfect hash validation - ensure we found the right prefix
+        let &(root_prefix, root) = self.long_roots.get(index)?;
+        if root == NO_ROOT || root_prefix != prefix {
+            return None;
+        }
+
+        let mut best_long_match = None;
+        let mut node = &self.nodes[root as usize];
+
+        for (depth, &byte) in data[TRIE_PREFIX_LEN..].iter().enumerate() {
+            let first_child = node.first_child as usize;
+            let children = &self.labels[first_child..first_child + node.n_children as usize];
+
+            match children.binary_search(&byte) {
+                Ok(position) => {
+                    node = &self.nodes[first_child + position];
+                    if let Some(id) = node.id {
+                        best_long_match = Some((id, TRIE_PREFIX_LEN + depth + 1));
+                    }
+                }
+                Err(_) => break,
+            }
+        }
+
+        best_long_match
+    }
+}
+
+/// Converts byte sequence to little-endian u64 with length masking
+///
+/// Interior positions use a single unaligned 8-byte load; near the end of the
+/// slice only the available bytes are read, so any slice is safe to pass.
+#[inline(always)]
+fn bytes_to_u64_le(bytes: &[u8], len: usize) -> u64 {
+    let value = match bytes.first_chunk::<8>() {
+        Some(chunk) => u64::from_le_bytes(*chunk),
+        None => {
+            let mut buffer = [0u8; 8];
+            buffer[..bytes.len()].copy_from_slice(bytes);
+            u64::from_le_bytes(buffer)
+        }
+    };
+
+    value & MASKS[len]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lpm::testing::{check_end_of_allocation, expected, SHORT_KEYS};
+
+    /// URLs whose windows make patterns sharing long prefixes
+    const TEXT: &[u8] = b"https://example.com/users/12345678?page=1 https://example.com/users/12345679?page=2 \
+        https://example.org/items/1234567890abcdef";
+
+    fn matcher(keys: &[&[u8]]) -> LongestPrefixMatcher<u16> {
+        let mut matcher = LongestPrefixMatcher::new();
+        for (id, key) in keys.iter().enumerate() {
+            matcher.insert(key, id as u16);
+        }
+        matcher
+    }
+
+    #[test]
+    fn short_data_at_end_of_allocation() {
+        // Patterns of 8 bytes are still short patterns for this matcher
+        let keys = [&SHORT_KEYS[..], &[b"abcdefgh"]].concat();
+        let matcher = matcher(&keys);
+        let finalized = matcher.finalize();
+        assert!(finalized.long_phf.is_none());
+
+        check_end_of_allocation(&keys, |data| matcher.find_longest_match(data));
+        check_end_of_allocation(&keys, |data| finalized.find_longest_match(data));
+    }
+
+    /// Checks both matchers against the patterns on every prefix of every suffix of `text`
+    fn check_prefixes(keys: &[&[u8]], matcher: &LongestPrefixMatcher<u16>, finalized: &StaticLongestPrefixMatcher<u16>, text: &[u8]) {
+        for start in 0..text.len() {
+            for end in start..=text.len() {
+                let data = &text[start..end];
+                let expected = expected(keys, data);
+                assert_eq!(matcher.find_longest_match(data), expected, "{:?}", data);
+                assert_eq!(finalized.find_longest_match(data), expected, "{:?}", data);
+            }
+        }
+    }
+
+    #[test]
+    fn static_matches_dynamic_without_long_keys() {
+        let keys = [&SHORT_KEYS[..], &[b"https://", b"example."]].concat();
+        let matcher = matcher(&keys);
+        let finalized = matcher.finalize();
+        assert!(finalized.long_phf.is_none());
+
+        check_prefixes(&keys, &matcher, &finalized, b"https://example.abcdefgh");
     }
-}
\ No newline at end of file
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn static_matches_dynamic() {
+        // Windows of every length around the 8-byte split, including exactly 8 and 16 bytes
+        let mut keys = Vec::new();
+        for length in [1, 2, 3, 5, 7, 8, 9, 12, 15, 16, 17, 24] {
+            keys.extend(TEXT.windows(length).step_by(3));
+        }
+        assert!(keys.iter().any(|key| key.len() == 8) && keys.iter().any(|key| key.len() == 16));
+
+        let matcher = matcher(&keys);
+        let finalized = matcher.finalize();
+        assert!(finalized.long_phf.is_some());
+
+        check_prefixes(&keys, &matcher, &finalized, TEXT);
+        for key in &keys {
+            assert_eq!(finalized.find_longest_match(key).map(|(_, length)| length), Some(key.len()));
+        }
+    }
+}
@@ -8,6 +8,7 @@
 //!
 //! Provides both dynamic (training) and static (parsing) implementations.
 
+use crate::lpm::hash_map_heap_size;
 use rustc_hash::FxHashMap;
 use ptr_hash::{bucket_fn::Linear, PtrHash, PtrHashParams};
 
@@ -115,6 +116,13 @@ impl LongestPrefixMatcher16{
         None
     }
 
+    /// Returns an estimate of the heap memory (in bytes) allocated by the matcher
+    pub fn heap_size(&self) -> usize {
+        hash_map_heap_size(&self.dictionary) +
+        hash_map_heap_size(&self.buckets) +
+        self.buckets.values().map(|bucket| bucket.capacity() * std::mem::size_of::<(u64, u8, u16)>()).sum::<usize>()
+    }
+
     /// Converts dynamic matcher to optimized static representation
     /// 
     /// Transitions from training-optimized data structures to parsing-optimized
@@ -186,7 +194,17 @@ impl LongestPrefixMatcher16{
             short_dictionary.insert((prefix, length), id);
         }
 
+        // Small dictionaries may have no patterns of 8 bytes or more to hash
         let prefixes = long_dictionary.keys().copied().collect::<Vec<_>>();
+        if prefixes.is_empty() {
+            return StaticLongestPrefixMatcher16 {
+                short_dictionary,
+                long_phf: None,
+                long_info: Vec::new(),
+                long_buckets,
+            };
+        }
+
         let mut params = PtrHashParams::default_fast();
         params.remap = false;
         let long_phf = PtrHash::new(&prefixes, params);
@@ -200,6 +218,8 @@ impl LongestPrefixMatcher16{
             long_info[index] = p;
         }
 
+        let long_phf = Some(long_phf);
+
         StaticLongestPrefixMatcher16 {
             short_dictionary,
             long_phf,
@@ -233,7 +253,7 @@ struct LongMatchInfo{
 /// memory indirection and cache misses.
 pub struct StaticLongestPrefixMatcher16{
     short_dictionary: FxHashMap<(u64, u8), u16>,    // Short pattern lookup table
-    long_phf: PtrHash<u64, Linear>,                 // Perfect hash for long pattern prefixes  
+    long_phf: Option<PtrHash<u64, Linear>>,         // Perfect hash for long pattern prefixes  
     long_info: Vec<LongMatchInfo>,                  // Long pattern metadata with inline storage
     long_buckets: Vec<(u64, u8, u16)>,              // Overflow storage for long patterns
 }
@@ -270,10 +290,19 @@ impl StaticLongestPrefixMatcher16{
         None
     }
 
+    /// Returns an estimate of the heap memory (in bytes) allocated by the matcher
+    ///
+    /// The perfect hash function is not counted; it takes a few bits per long pattern prefix.
+    pub fn heap_size(&self) -> usize {
+        hash_map_heap_size(&self.short_dictionary) +
+        self.long_info.capacity() * std::mem::size_of::<LongMatchInfo>() +
+        self.long_buckets.capacity() * std::mem::size_of::<(u64, u8, u16)>()
+    }
+
     /// Optimized long pattern resolution with inline storage
     #[inline]
     pub fn compute_long_answer(&self, prefix: u64, suffix: u64, suffix_len: usize) -> Option<(u16, usize)> {
-        let index = self.long_phf.index_no_remap(&prefix);
+        let index = self.long_phf.as_ref()?.index_no_remap(&prefix);
 
         // Perfect hash validation - ensure we found the right prefix
         if index >= self.long_info.len() || prefix != self.long_info[index].prefix {
@@ -311,11 +340,18 @@ impl StaticLongestPrefixMatcher16{
 }
 
 /// Converts byte sequence to little-endian u64 with length masking
+///
+/// Interior positions use a single unaligned 8-byte load; near the end of the
+/// slice only the available bytes are read, so any slice is safe to pass.
 #[inline(always)]
 fn bytes_to_u64_le(bytes: &[u8], len: usize) -> u64 {
-    let ptr = bytes.as_ptr();
-    let value = unsafe {
-        *(ptr as *const u64)
+    let value = match bytes.first_chunk::<8>() {
+        Some(chunk) => u64::from_le_bytes(*chunk),
+        None => {
+            let mut buffer = [0u8; 8];
+            buffer[..bytes.len()].copy_from_slice(bytes);
+            u64::from_le_bytes(buffer)
+        }
     };
 
     value & MASKS[len]
@@ -332,3 +368,22 @@ fn is_prefix(text: u64, prefix: u64, text_size: usize, prefix_size: usize) -> bo
 fn shared_prefix_size(a: u64, b: u64) -> usize {
     ((a ^ b).trailing_zeros() >> 3) as usize
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lpm::testing::{check_end_of_allocation, SHORT_KEYS};
+
+    #[test]
+    fn short_data_at_end_of_allocation() {
+        let mut matcher = LongestPrefixMatcher16::new();
+        for (id, key) in SHORT_KEYS.iter().enumerate() {
+            assert!(matcher.insert(key, id as u16));
+        }
+        let finalized = matcher.finalize();
+        assert!(finalized.long_phf.is_none());
+
+        check_end_of_allocation(&SHORT_KEYS, |data| matcher.find_longest_match(data));
+        check_end_of_allocation(&SHORT_KEYS, |data| finalized.find_longest_match(data));
+    }
+}
@@ -1,5 +1,21 @@
 pub mod lpm;
 pub mod lpm16;
+#[cfg(test)]
+mod testing;
 
-pub use lpm::LongestPrefixMatcher;
-pub use lpm16::{LongestPrefixMatcher16, StaticLongestPrefixMatcher16};
\ No newline at end of file
+pub use lpm::{LongestPrefixMatcher, StaticLongestPrefixMatcher};
+pub use lpm16::{LongestPrefixMatcher16, StaticLongestPrefixMatcher16};
+
+use std::collections::HashMap;
+
+/// Estimates the heap memory (in bytes) allocated by a hash map, excluding memory owned by its entries
+///
+/// Assumes the SwissTable layout of the standard library: a power-of-two number of buckets,
+/// at most 7/8 of them full, each with one control byte, plus a trailing group of 16 control bytes.
+pub(crate) fn hash_map_heap_size<K, V, S>(map: &HashMap<K, V, S>) -> usize {
+    if map.capacity() == 0 {
+        return 0;
+    }
+    let buckets = (map.capacity() * 8 / 7).next_power_of_two();
+    buckets * (std::mem::size_of::<(K, V)>() + 1) + 16
+}
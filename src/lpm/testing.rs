@@ -0,0 +1,27 @@
+//! Test helpers shared by the matcher tests
+
+/// Patterns shorter than 8 bytes, so that neither static matcher has a perfect hash
+pub(crate) const SHORT_KEYS: [&[u8]; 6] = [b"a", b"b", b"ab", b"abc", b"abcdefg", b"bcdefgh"];
+
+/// Returns the longest of `keys` that is a prefix of `data`, with its index and length
+pub(crate) fn expected(keys: &[&[u8]], data: &[u8]) -> Option<(u16, usize)> {
+    keys.iter()
+        .enumerate()
+        .filter(|(_, key)| data.starts_with(key))
+        .max_by_key(|(_, key)| key.len())
+        .map(|(id, key)| (id as u16, key.len()))
+}
+
+/// Checks a matcher of `keys` on every suffix of each key preceded by one byte
+///
+/// Each string is copied into an allocation of exactly its length, so that every
+/// lookup reads fewer than 8 bytes before the end of the allocation.
+pub(crate) fn check_end_of_allocation(keys: &[&[u8]], find_longest_match: impl Fn(&[u8]) -> Option<(u16, usize)>) {
+    for key in keys {
+        let data: Box<[u8]> = [b"x", *key].concat().into_boxed_slice();
+        for start in 0..=data.len() {
+            let tail = &data[start..];
+            assert_eq!(find_longest_match(tail), expected(keys, tail), "{:?}", tail);
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use onpair_rs::{CompressedStrings, OnPair, OnPair16};
+use std::sync::Arc;
+use std::thread;
+
+const N_THREADS: usize = 4;
+
+/// Simulates a column of URLs
+fn strings() -> Vec<String> {
+    (0..20_000)
+        .map(|i| format!("https://example.com/users/{}/posts/{}", i % 1000, i))
+        .collect()
+}
+
+/// Reads every string of a shared collection from several threads at once
+fn read_concurrently<C: CompressedStrings + Send + Sync + 'static>(collection: C, strings: &Arc<Vec<String>>) {
+    let collection = Arc::new(collection);
+
+    let handles: Vec<_> = (0..N_THREADS)
+        .map(|t| {
+            let collection = Arc::clone(&collection);
+            let strings = Arc::clone(strings);
+
+            thread::spawn(move || {
+                // Each thread starts at a different string, so that the threads read the same
+                // strings at the same time
+                let mut buffer = Vec::with_capacity(256);
+                for i in (0..strings.len()).map(|i| (i + t * strings.len() / N_THREADS) % strings.len()) {
+                    buffer.clear();
+                    collection.decompress_into(i, &mut buffer);
+                    assert_eq!(buffer, strings[i].as_bytes());
+                }
+            })
+        })
+        .collect();
+
+    for handle in handles {
+        handle.join().unwrap();
+    }
+}
+
+#[test]
+fn onpair_concurrent_reads() {
+    let strings = Arc::new(strings());
+
+    let mut onpair = OnPair::new(5);
+    onpair.compress_strings(&strings);
+    let mut bytes = Vec::new();
+    onpair.serialize(&mut bytes).unwrap();
+
+    read_concurrently(onpair, &strings);
+    read_concurrently(OnPair::deserialize(&bytes[..]).unwrap(), &strings);
+}
+
+#[test]
+fn onpair16_concurrent_reads() {
+    let strings = Arc::new(strings());
+
+    let mut onpair16 = OnPair16::new(5);
+    onpair16.compress_strings(&strings);
+    let mut bytes = Vec::new();
+    onpair16.serialize(&mut bytes).unwrap();
+
+    read_concurrently(onpair16, &strings);
+    read_concurrently(OnPair16::deserialize(&bytes[..]).unwrap(), &strings);
+}